@@ -23,14 +23,14 @@ use std::cell::RefCell;
 
 use self::structs::{CArray, CAtom, CEdge, CEventData, CId, CNode, COption, CPair, CResult, CTriple, CUnit};
 use crate::{
-  store::Store,
+  store::{Pragmas, Store},
   workspace::{Constraints, Workspace},
   StoreError, Transactor,
 };
 
 thread_local! {
   static CONSTRAINTS: RefCell<Constraints> = RefCell::new(Constraints::new());
-  static STORE: RefCell<Option<Store>> = RefCell::new(None);
+  static STORE: RefCell<Option<Store>> = const { RefCell::new(None) };
 }
 
 pub fn convert_result<T>(f: impl FnOnce() -> Result<T, StoreError>) -> CResult<T> {
@@ -80,16 +80,7 @@ pub unsafe extern "C" fn dust_open(len: u64, ptr: *mut u8) -> CResult<CUnit> {
     let path = CArray(len, ptr).as_ref();
     let path = std::str::from_utf8(path).map_err(|_| StoreError::InvalidUtf8)?;
     let conn = Connection::open(path)?;
-    conn.execute_batch(
-      "
-      PRAGMA auto_vacuum = INCREMENTAL;
-      PRAGMA journal_mode = WAL;
-      PRAGMA synchronous = NORMAL;
-      PRAGMA wal_autocheckpoint = 2000;
-      PRAGMA cache_size = 2000;
-      PRAGMA busy_timeout = 1000;
-      ",
-    )?;
+    Pragmas::default().apply(&conn)?;
     let store = Store::new(conn, CONSTRAINTS.with(|cell| cell.borrow().clone()))?;
     STORE.with(|cell| cell.replace(Some(store)));
     Ok(CUnit(0))
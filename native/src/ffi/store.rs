@@ -40,7 +40,7 @@ pub extern "C" fn dust_node_id_by_label(label: u64) -> CResult<CArray<CId>> {
 pub extern "C" fn dust_atom(idh: u64, idl: u64) -> CResult<COption<CAtom>> {
   access_workspace(|txr, ws| {
     let id = CId(idh, idl).into();
-    Ok(ws.atom(txr, id).map(Into::into).into())
+    Ok(ws.atom(txr, id)?.map(Into::into).into())
   })
 }
 
@@ -52,7 +52,7 @@ pub extern "C" fn dust_atom_id_label_value_by_src(
   access_workspace(|txr, ws| {
     let src = CId(srch, srcl).into();
     Ok(
-      ws.atom_id_label_value_by_src(txr, src)
+      ws.atom_id_label_value_by_src(txr, src)?
         .into_iter()
         .map(|(id, (label, value))| CTriple(id.into(), label, value.into()))
         .collect::<Box<[_]>>()
@@ -70,7 +70,7 @@ pub extern "C" fn dust_atom_id_value_by_src_label(
   access_workspace(|txr, ws| {
     let src = CId(srch, srcl).into();
     Ok(
-      ws.atom_id_value_by_src_label(txr, src, label)
+      ws.atom_id_value_by_src_label(txr, src, label)?
         .into_iter()
         .map(|(id, value)| CPair(id.into(), value.into()))
         .collect::<Box<[_]>>()
@@ -83,7 +83,7 @@ pub extern "C" fn dust_atom_id_value_by_src_label(
 pub extern "C" fn dust_atom_id_src_value_by_label(label: u64) -> CResult<CArray<CTriple<CId, CId, CArray<u8>>>> {
   access_workspace(|txr, ws| {
     Ok(
-      ws.atom_id_src_value_by_label(txr, label)
+      ws.atom_id_src_value_by_label(txr, label)?
         .into_iter()
         .map(|(id, (src, value))| CTriple(id.into(), src.into(), value.into()))
         .collect::<Box<[_]>>()
@@ -101,7 +101,7 @@ pub unsafe extern "C" fn dust_atom_id_src_by_label_value(
   access_workspace(|txr, ws| {
     let value = CArray(len, ptr).as_ref();
     Ok(
-      ws.atom_id_src_by_label_value(txr, label, value)
+      ws.atom_id_src_by_label_value(txr, label, value)?
         .into_iter()
         .map(|(id, src)| CPair(id.into(), src.into()))
         .collect::<Box<[_]>>()
@@ -196,7 +196,7 @@ pub extern "C" fn dust_set_node_some(idh: u64, idl: u64, label: u64) -> CResult<
 pub extern "C" fn dust_set_atom_none(idh: u64, idl: u64) -> CResult<CUnit> {
   access_workspace(|txr, ws| {
     let id = CId(idh, idl).into();
-    ws.set_atom(txr, id, None);
+    ws.set_atom(txr, id, None)?;
     Ok(CUnit(0))
   })
 }
@@ -215,7 +215,7 @@ pub unsafe extern "C" fn dust_set_atom_some(
     let id = CId(idh, idl).into();
     let src = CId(srch, srcl).into();
     let value = CArray(len, ptr).as_ref();
-    ws.set_atom(txr, id, Some((src, label, Vec::from(value).into())));
+    ws.set_atom(txr, id, Some((src, label, Vec::from(value).into())))?;
     Ok(CUnit(0))
   })
 }
@@ -257,7 +257,7 @@ pub extern "C" fn dust_sync_version() -> CResult<CArray<u8>> {
 pub unsafe extern "C" fn dust_sync_actions(len: u64, ptr: *mut u8) -> CResult<CArray<u8>> {
   access_workspace(|txr, ws| {
     let version = CArray(len, ptr).as_ref();
-    Ok(ws.sync_actions(txr, version).into())
+    Ok(ws.sync_actions(txr, version)?.into())
   })
 }
 
@@ -265,12 +265,12 @@ pub unsafe extern "C" fn dust_sync_actions(len: u64, ptr: *mut u8) -> CResult<CA
 pub unsafe extern "C" fn dust_sync_join(len: u64, ptr: *mut u8) -> CResult<CUnit> {
   access_workspace(|txr, ws| {
     let actions = CArray(len, ptr).as_ref();
-    ws.sync_join(txr, actions);
+    ws.sync_join(txr, actions)?;
     Ok(CUnit(0))
   })
 }
 
 #[no_mangle]
 pub extern "C" fn dust_barrier() -> CResult<CArray<CEventData>> {
-  access_workspace(|txr, ws| Ok(ws.barrier(txr).into()))
+  access_workspace(|txr, ws| Ok(ws.barrier(txr)?.into()))
 }
@@ -165,6 +165,6 @@ impl<T> CArray<T> {
   }
 
   pub unsafe fn into_boxed(self) -> Box<[T]> {
-    Box::from_raw(std::slice::from_raw_parts_mut(self.1, self.0 as usize))
+    Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.1, self.0 as usize))
   }
 }
@@ -36,8 +36,43 @@ pub enum StoreError {
   Uninitialised,
   #[error("data store disconnected due to previous error")]
   Disconnected,
+  #[error("data store is read-only")]
+  ReadOnly,
+  #[error("invalid data: unrecognised header")]
+  InvalidHeader,
+  #[error("invalid data: unsupported format version {0}")]
+  UnsupportedVersion(u8),
+  #[error("invalid data: checksum mismatch")]
+  ChecksumMismatch,
+  #[error("invalid data: {0}")]
+  Deserialize(#[from] Box<ErrorKind>),
+  #[error("another replica has the same replica ID")]
+  DuplicateReplica,
+  #[error("metadata blobs not enabled for this structure")]
+  MetaNotEnabled,
+  #[error("invalid atom value: {0}")]
+  Decode(#[from] DecodeError),
+  #[error("no workspace named {0}")]
+  UnknownWorkspace(String),
 }
 
+/// Error from decoding a typed atom value (see [`workspace::Workspace::atom_u64`]).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+  #[error("expected {expected} bytes, found {found}")]
+  InvalidLength { expected: usize, found: usize },
+  #[error("string is not valid UTF8")]
+  InvalidUtf8,
+  #[error("invalid boolean byte: {0}")]
+  InvalidBool(u8),
+}
+
+/// Leading byte of all framed blobs.
+pub const FRAME_MAGIC: u8 = 0xd5;
+
+/// Version of the framing format.
+pub const FRAME_VERSION: u8 = 1;
+
 /// A wrapper around `bincode`.
 pub fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, Box<ErrorKind>> {
   bincode::options().reject_trailing_bytes().with_fixint_encoding().with_big_endian().serialize(value)
@@ -48,6 +83,56 @@ pub fn deserialize<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, Box<Err
   bincode::options().reject_trailing_bytes().with_fixint_encoding().with_big_endian().deserialize(bytes)
 }
 
+/// Wraps `payload` as `[FRAME_MAGIC, FRAME_VERSION, ..payload, ..crc32]`, so
+/// that truncated or corrupted blobs can be detected before deserialisation.
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+  let mut res = Vec::with_capacity(payload.len() + 6);
+  res.push(FRAME_MAGIC);
+  res.push(FRAME_VERSION);
+  res.extend_from_slice(payload);
+  res.extend_from_slice(&crc32(&res).to_be_bytes());
+  res
+}
+
+/// Verifies and strips the header and trailer added by [`frame`]. Framed blobs
+/// of another format version fail with
+/// [`StoreError::UnsupportedVersion`], so callers can ask for an upgrade.
+///
+/// Blobs starting with a zero byte are taken to be from peers which predate
+/// framing (version 0), and are returned as they are, as the payload format
+/// has not changed since: these always start with a big-endian length, whose
+/// leading byte is zero in practice. Anything else not starting with
+/// [`FRAME_MAGIC`] fails with [`StoreError::InvalidHeader`].
+pub fn unframe(bytes: &[u8]) -> Result<&[u8], StoreError> {
+  if bytes.first() == Some(&0) {
+    return Ok(bytes);
+  }
+  if bytes.len() < 6 || bytes[0] != FRAME_MAGIC {
+    return Err(StoreError::InvalidHeader);
+  }
+  if bytes[1] != FRAME_VERSION {
+    return Err(StoreError::UnsupportedVersion(bytes[1]));
+  }
+  let (body, trailer) = bytes.split_at(bytes.len() - 4);
+  if crc32(body).to_be_bytes() != trailer {
+    return Err(StoreError::ChecksumMismatch);
+  }
+  Ok(&body[2..])
+}
+
+/// Computes the CRC-32 (IEEE) checksum of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+  const POLY: u32 = 0xedb88320;
+  let mut res = !0u32;
+  for &byte in bytes {
+    res ^= byte as u32;
+    for _ in 0..8 {
+      res = if res & 1 != 0 { (res >> 1) ^ POLY } else { res >> 1 };
+    }
+  }
+  !res
+}
+
 /// A wrapper around `rusqlite`.
 pub struct Transactor {
   conn: Connection,
@@ -66,11 +151,28 @@ impl DerefMut for Transactor {
   }
 }
 
+impl Transactor {
+  /// Starts a write transaction, giving `conn` back on failure (in the same way
+  /// as [`Connection::close`]).
+  #[allow(clippy::result_large_err)]
+  pub fn begin(conn: Connection) -> Result<Self, (Connection, rusqlite::Error)> {
+    match conn.execute_batch("BEGIN IMMEDIATE") {
+      Ok(()) => Ok(Self { conn }),
+      Err(err) => Err((conn, err)),
+    }
+  }
+
+  /// Starts a read transaction instead, and rejects all writes on `conn`.
+  pub fn read_only(conn: Connection) -> rusqlite::Result<Self> {
+    conn.execute_batch("PRAGMA query_only = ON; BEGIN DEFERRED")?;
+    Ok(Self { conn })
+  }
+}
+
 impl TryFrom<Connection> for Transactor {
   type Error = rusqlite::Error;
   fn try_from(value: Connection) -> rusqlite::Result<Self> {
-    value.execute_batch("BEGIN IMMEDIATE")?;
-    Ok(Self { conn: value })
+    Self::begin(value).map_err(|(_, err)| err)
   }
 }
 
@@ -128,6 +230,47 @@ mod tests {
     assert_eq!(deserialize::<Option<i64>>(&[1, 255, 255, 255, 255, 255, 255, 255, 255]).unwrap(), Some(-1));
   }
 
+  #[test]
+  fn frame_simple() {
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    let framed = frame(&[1, 2, 3]);
+    assert_eq!(unframe(&framed).unwrap(), &[1, 2, 3]);
+    assert_eq!(unframe(&frame(&[])).unwrap(), &[] as &[u8]);
+    assert!(matches!(unframe(&framed[..framed.len() - 1]), Err(StoreError::ChecksumMismatch)));
+    assert!(matches!(unframe(&framed[..3]), Err(StoreError::InvalidHeader)));
+    assert_eq!(unframe(&[0, 1, 2]).unwrap(), &[0, 1, 2]);
+    assert!(matches!(unframe(&[1, 2, 3, 4, 5, 6, 7]), Err(StoreError::InvalidHeader)));
+    let mut corrupted = framed.clone();
+    corrupted[3] ^= 0x10;
+    assert!(matches!(unframe(&corrupted), Err(StoreError::ChecksumMismatch)));
+    let mut corrupted = framed.clone();
+    corrupted[0] ^= 0x01;
+    assert!(matches!(unframe(&corrupted), Err(StoreError::InvalidHeader)));
+  }
+
+  #[test]
+  fn frame_version() {
+    // `sync_version` of an empty workspace, as sent by peers which predate
+    // framing: a bare map from structure names to versions.
+    let mut v0 = 3u64.to_be_bytes().to_vec();
+    for name in ["atoms", "edges", "nodes"] {
+      v0.extend((name.len() as u64).to_be_bytes());
+      v0.extend(name.as_bytes());
+      v0.extend(8u64.to_be_bytes());
+      v0.extend([0; 8]);
+    }
+    assert_eq!(unframe(&v0).unwrap(), &v0[..]);
+    let all: std::collections::BTreeMap<String, Vec<u8>> = deserialize(unframe(&v0).unwrap()).unwrap();
+    assert_eq!(all.keys().collect::<Vec<_>>(), ["atoms", "edges", "nodes"]);
+
+    let framed = frame(&v0);
+    assert_eq!(framed[1], FRAME_VERSION);
+    assert_eq!(unframe(&framed).unwrap(), &v0[..]);
+    let mut v2 = framed.clone();
+    v2[1] = FRAME_VERSION + 1;
+    assert!(matches!(unframe(&v2), Err(StoreError::UnsupportedVersion(v)) if v == FRAME_VERSION + 1));
+  }
+
   /*
   #[test]
   fn multimap_simple() {
@@ -12,32 +12,461 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rusqlite::Connection;
+use rusqlite::{Connection, ErrorCode};
+use std::{collections::BTreeMap, time::Duration};
 
-use crate::workspace::{Constraints, Workspace};
-use crate::{StoreError, Transactor};
+use crate::ffi::structs::CEventData;
+use crate::workspace::{Anomaly, Constraints, Metrics, Op, SyncStats, Workspace};
+use crate::{deserialize, frame, serialize, unframe, StoreError, Transactor};
 
 pub struct Store {
   txr: Option<Transactor>,
+  constraints: Constraints,
   workspace: Workspace,
+  named: BTreeMap<String, Workspace>,
+  read_only: bool,
+  retry: Retry,
+}
+
+/// Value of `PRAGMA synchronous` (see <https://www.sqlite.org/pragma.html#pragma_synchronous>).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+  Off,
+  Normal,
+  Full,
+  Extra,
+}
+
+/// Connection settings, applied by [`Pragmas::apply`] before the connection
+/// is handed to [`Store::new`]. The journal is always in WAL mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pragmas {
+  pub synchronous: Synchronous,
+  pub wal_autocheckpoint: u64,
+  pub cache_size: i64,
+  pub busy_timeout: u64,
+  /// Encryption key, applied before anything else is read. A wrong key makes
+  /// [`Pragmas::apply`] fail with `SQLITE_NOTADB`.
+  #[cfg(feature = "sqlcipher")]
+  pub key: Option<String>,
+}
+
+impl Default for Pragmas {
+  fn default() -> Self {
+    Self {
+      synchronous: Synchronous::Normal,
+      wal_autocheckpoint: 2000,
+      cache_size: 2000,
+      busy_timeout: 1000,
+      #[cfg(feature = "sqlcipher")]
+      key: None,
+    }
+  }
+}
+
+impl Pragmas {
+  /// Applies settings to a newly opened connection. Can be repeated on every
+  /// open: the persistent ones (`auto_vacuum`, `journal_mode`) are no-ops once
+  /// in effect. In-memory databases cannot use WAL and silently keep their
+  /// `memory` journal, which is fine as no other connection can see them.
+  pub fn apply(&self, conn: &Connection) -> Result<(), StoreError> {
+    #[cfg(feature = "sqlcipher")]
+    if let Some(key) = &self.key {
+      conn.pragma_update(None, "key", key)?;
+      conn.query_row("SELECT COUNT(*) FROM sqlite_schema", (), |_| Ok(()))?;
+    }
+    let synchronous = match self.synchronous {
+      Synchronous::Off => "OFF",
+      Synchronous::Normal => "NORMAL",
+      Synchronous::Full => "FULL",
+      Synchronous::Extra => "EXTRA",
+    };
+    conn.execute_batch(&format!(
+      "
+      PRAGMA auto_vacuum = INCREMENTAL;
+      PRAGMA journal_mode = WAL;
+      PRAGMA synchronous = {synchronous};
+      PRAGMA wal_autocheckpoint = {};
+      PRAGMA cache_size = {};
+      PRAGMA busy_timeout = {};
+      ",
+      self.wal_autocheckpoint, self.cache_size, self.busy_timeout
+    ))?;
+    Ok(())
+  }
+}
+
+/// Result of [`Store::check_integrity`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+  /// Problems reported by `PRAGMA integrity_check`.
+  pub sqlite: Vec<String>,
+  /// Problems with the data itself (see [`Workspace::check_integrity`]).
+  pub anomalies: Vec<Anomaly>,
+}
+
+impl IntegrityReport {
+  pub fn is_ok(&self) -> bool {
+    self.sqlite.is_empty() && self.anomalies.is_empty()
+  }
+}
+
+/// Result of [`Store::maintain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Maintenance {
+  /// Number of pages the database file shrank by.
+  pub pages_reclaimed: u64,
+}
+
+/// How to retry starting a write transaction while another connection holds
+/// the lock, after waiting for [`Pragmas::busy_timeout`] each time. Waits are
+/// doubled after each attempt, up to `max_backoff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+  pub attempts: u32,
+  pub initial_backoff: Duration,
+  pub max_backoff: Duration,
+}
+
+impl Default for Retry {
+  fn default() -> Self {
+    Self { attempts: 5, initial_backoff: Duration::from_millis(10), max_backoff: Duration::from_millis(500) }
+  }
+}
+
+impl Retry {
+  fn begin(&self, mut conn: Connection) -> Result<Transactor, StoreError> {
+    let mut backoff = self.initial_backoff;
+    let mut attempts = 0;
+    loop {
+      match Transactor::begin(conn) {
+        Ok(txr) => return Ok(txr),
+        Err((conn_, err))
+          if attempts < self.attempts
+            && matches!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)) =>
+        {
+          std::thread::sleep(backoff);
+          backoff = (backoff * 2).min(self.max_backoff);
+          attempts += 1;
+          conn = conn_;
+        }
+        Err((_, err)) => return Err(err.into()),
+      }
+    }
+  }
 }
 
 impl Store {
   pub fn new(conn: Connection, constraints: Constraints) -> Result<Self, StoreError> {
-    let mut txr = conn.try_into()?;
-    let workspace = Workspace::new("", constraints, &mut txr);
-    Ok(Self { txr: Some(txr), workspace })
+    Self::new_with_retry(conn, constraints, Retry::default())
+  }
+
+  /// Creates a store on a new private in-memory database, with default
+  /// [`Pragmas`]. Everything is lost once the store is dropped, but it
+  /// otherwise behaves like any other store, including syncing with others.
+  pub fn in_memory(constraints: Constraints) -> Result<Self, StoreError> {
+    let conn = Connection::open_in_memory()?;
+    Pragmas::default().apply(&conn)?;
+    Self::new(conn, constraints)
+  }
+
+  /// Same as [`Self::new`], using `retry` for this and every later transaction.
+  pub fn new_with_retry(conn: Connection, constraints: Constraints, retry: Retry) -> Result<Self, StoreError> {
+    let mut txr = retry.begin(conn)?;
+    let workspace = Workspace::new("", constraints.clone(), &mut txr)?;
+    Ok(Self { txr: Some(txr), constraints, workspace, named: BTreeMap::new(), read_only: false, retry })
+  }
+
+  /// Opens an existing store for reading only: [`Self::as_mut`] and everything
+  /// using it fail with [`StoreError::ReadOnly`], and the connection itself
+  /// rejects writes. Reads see a snapshot as of the last [`Self::commit`], which
+  /// here just starts a new snapshot.
+  pub fn open_read_only(conn: Connection, constraints: Constraints) -> Result<Self, StoreError> {
+    let txr = Transactor::read_only(conn)?;
+    let workspace = Workspace::load("", constraints.clone(), &txr)?;
+    let named = BTreeMap::new();
+    Ok(Self { txr: Some(txr), constraints, workspace, named, read_only: true, retry: Retry::default() })
+  }
+
+  pub fn as_ref(&self) -> Result<(&Transactor, &Workspace), StoreError> {
+    let txr = self.txr.as_ref().ok_or(StoreError::Disconnected)?;
+    Ok((txr, &self.workspace))
   }
 
   pub fn as_mut(&mut self) -> Result<(&mut Transactor, &mut Workspace), StoreError> {
+    if self.read_only {
+      return Err(StoreError::ReadOnly);
+    }
     let txr = self.txr.as_mut().ok_or(StoreError::Disconnected)?;
     Ok((txr, &mut self.workspace))
   }
 
+  /// Adds a separate workspace called `name`, creating it if it does not exist
+  /// yet, for data to be kept apart from the default one (which is called
+  /// `""`). It has tables and constraints of its own, and is accessed through
+  /// [`Self::as_ref_named`] and [`Self::as_mut_named`], but is synced along
+  /// with all others by [`Self::sync_version`] and the like. Adding a workspace
+  /// again does nothing.
+  pub fn add_workspace(&mut self, name: &str) -> Result<(), StoreError> {
+    if name.is_empty() || self.named.contains_key(name) {
+      return Ok(());
+    }
+    let txr = self.txr.as_mut().ok_or(StoreError::Disconnected)?;
+    let workspace = if self.read_only {
+      Workspace::load(name, self.constraints.clone(), txr)?
+    } else {
+      Workspace::new(name, self.constraints.clone(), txr)?
+    };
+    self.named.insert(name.to_owned(), workspace);
+    Ok(())
+  }
+
+  /// Same as [`Self::as_ref`], for the workspace added as `name`. Fails with
+  /// [`StoreError::UnknownWorkspace`] if there is none.
+  pub fn as_ref_named(&self, name: &str) -> Result<(&Transactor, &Workspace), StoreError> {
+    let txr = self.txr.as_ref().ok_or(StoreError::Disconnected)?;
+    match name {
+      "" => Ok((txr, &self.workspace)),
+      _ => Ok((txr, self.named.get(name).ok_or_else(|| StoreError::UnknownWorkspace(name.to_owned()))?)),
+    }
+  }
+
+  /// Same as [`Self::as_mut`], for the workspace added as `name`. Fails with
+  /// [`StoreError::UnknownWorkspace`] if there is none.
+  pub fn as_mut_named(&mut self, name: &str) -> Result<(&mut Transactor, &mut Workspace), StoreError> {
+    if self.read_only {
+      return Err(StoreError::ReadOnly);
+    }
+    let txr = self.txr.as_mut().ok_or(StoreError::Disconnected)?;
+    match name {
+      "" => Ok((txr, &mut self.workspace)),
+      _ => Ok((txr, self.named.get_mut(name).ok_or_else(|| StoreError::UnknownWorkspace(name.to_owned()))?)),
+    }
+  }
+
+  fn workspaces(&self) -> impl Iterator<Item = (&str, &Workspace)> {
+    std::iter::once(("", &self.workspace)).chain(self.named.iter().map(|(name, ws)| (name.as_str(), ws)))
+  }
+
+  /// Same as [`Workspace::sync_version`], covering all workspaces of the store
+  /// (see [`Self::add_workspace`]) in one version.
+  pub fn sync_version(&self) -> Result<Box<[u8]>, StoreError> {
+    let txr = self.txr.as_ref().ok_or(StoreError::Disconnected)?;
+    let all: BTreeMap<&str, Box<[u8]>> = self.workspaces().map(|(name, ws)| (name, ws.sync_version(txr))).collect();
+    Ok(frame(&serialize(&all).unwrap()).into())
+  }
+
+  /// Same as [`Workspace::sync_actions`], given a version from
+  /// [`Self::sync_version`]. Workspaces missing from `version` are sent in
+  /// full.
+  pub fn sync_actions(&self, version: &[u8]) -> Result<Box<[u8]>, StoreError> {
+    let txr = self.txr.as_ref().ok_or(StoreError::Disconnected)?;
+    let versions: BTreeMap<String, &[u8]> = deserialize(unframe(version)?)?;
+    let empty = frame(&serialize(&BTreeMap::<&str, Vec<u8>>::new()).unwrap());
+    let mut all: BTreeMap<&str, Box<[u8]>> = BTreeMap::new();
+    for (name, ws) in self.workspaces() {
+      all.insert(name, ws.sync_actions(txr, versions.get(name).copied().unwrap_or(&empty))?);
+    }
+    Ok(frame(&serialize(&all).unwrap()).into())
+  }
+
+  /// Same as [`Workspace::sync_join`], given actions from [`Self::sync_actions`].
+  /// Actions for workspaces not added here are ignored. Returns the total
+  /// counts over all workspaces.
+  pub fn sync_join(&mut self, actions: &[u8]) -> Result<SyncStats, StoreError> {
+    if self.read_only {
+      return Err(StoreError::ReadOnly);
+    }
+    let all: BTreeMap<String, &[u8]> = deserialize(unframe(actions)?)?;
+    let txr = self.txr.as_mut().ok_or(StoreError::Disconnected)?;
+    let workspaces =
+      std::iter::once(("", &mut self.workspace)).chain(self.named.iter_mut().map(|(n, ws)| (n.as_str(), ws)));
+    let mut res = SyncStats::default();
+    for (name, ws) in workspaces {
+      if let Some(actions) = all.get(name) {
+        res += ws.sync_join(txr, actions)?;
+      }
+    }
+    Ok(res)
+  }
+
+  /// Issues a barrier on every workspace, returning events for each by name.
+  pub fn barrier(&mut self) -> Result<BTreeMap<String, Vec<CEventData>>, StoreError> {
+    if self.read_only {
+      return Err(StoreError::ReadOnly);
+    }
+    let txr = self.txr.as_mut().ok_or(StoreError::Disconnected)?;
+    let workspaces =
+      std::iter::once(("", &mut self.workspace)).chain(self.named.iter_mut().map(|(n, ws)| (n.as_str(), ws)));
+    let mut res = BTreeMap::new();
+    for (name, ws) in workspaces {
+      res.insert(name.to_owned(), ws.barrier(txr)?);
+    }
+    Ok(res)
+  }
+
+  /// See [`Workspace::this`].
+  pub fn replica_id(&self) -> u64 {
+    self.workspace.this()
+  }
+
+  /// See [`Workspace::metrics`]. Counters restart from zero after
+  /// [`Self::rollback`], as the workspace is reloaded.
+  pub fn metrics(&self) -> Metrics {
+    self.workspace.metrics()
+  }
+
+  /// See [`Workspace::apply_batch`].
+  pub fn apply_batch(&mut self, ops: Vec<Op>) -> Result<Vec<CEventData>, StoreError> {
+    let (txr, workspace) = self.as_mut()?;
+    workspace.apply_batch(txr, ops)
+  }
+
+  /// See [`Workspace::traverse`].
+  pub fn traverse(&self, start: u128, label: u64, max_depth: usize) -> Result<Vec<(u128, usize)>, StoreError> {
+    let (txr, workspace) = self.as_ref()?;
+    Ok(workspace.traverse(txr, start, label, max_depth))
+  }
+
+  /// See [`Workspace::structure_digest`].
+  pub fn structure_digest(&self, name: &str) -> Result<Option<u128>, StoreError> {
+    let (txr, workspace) = self.as_ref()?;
+    workspace.structure_digest(txr, name)
+  }
+
+  /// See [`Workspace::recover`].
+  pub fn recover(&mut self) -> Result<bool, StoreError> {
+    let (txr, workspace) = self.as_mut()?;
+    workspace.recover(txr)
+  }
+
   pub fn commit(&mut self) -> Result<(), StoreError> {
+    if self.read_only {
+      return self.restart("COMMIT; BEGIN DEFERRED");
+    }
+    let txr = self.txr.take().ok_or(StoreError::Disconnected)?;
+    let conn: Connection = txr.try_into()?;
+    self.txr = Some(self.retry.begin(conn)?);
+    Ok(())
+  }
+
+  /// Checks the database file with `PRAGMA integrity_check`, then the data
+  /// with [`Workspace::check_integrity`]. Anomalies in the data are listed
+  /// individually, so that they can be repaired.
+  pub fn check_integrity(&self) -> Result<IntegrityReport, StoreError> {
+    let (txr, workspace) = self.as_ref()?;
+    let mut stmt = txr.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map((), |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+    let sqlite = rows.into_iter().filter(|row| row != "ok").collect();
+    Ok(IntegrityReport { sqlite, anomalies: workspace.check_integrity(txr)? })
+  }
+
+  /// Commits, then refreshes query planner statistics (`ANALYZE` and
+  /// `PRAGMA optimize`) and returns free pages to the file system, outside of
+  /// any transaction. With `vacuum`, the whole file is rebuilt with `VACUUM`,
+  /// which reclaims more but takes time and space proportional to the size
+  /// of the database; otherwise only an incremental vacuum is done.
+  ///
+  /// Pending modifications are kept, as in [`Self::commit`]. Safe to call
+  /// periodically, e.g. when the app is idle.
+  pub fn maintain(&mut self, vacuum: bool) -> Result<Maintenance, StoreError> {
+    if self.read_only {
+      return Err(StoreError::ReadOnly);
+    }
     let txr = self.txr.take().ok_or(StoreError::Disconnected)?;
     let conn: Connection = txr.try_into()?;
-    let txr: Transactor = conn.try_into()?;
+    let res = run_maintenance(&conn, vacuum);
+    self.txr = Some(self.retry.begin(conn)?);
+    res
+  }
+
+  /// Copies all data, including pending modifications, into a new store on
+  /// `conn`. The copy has its own replica ID, so both sides can be changed
+  /// independently and later merged with the usual sync methods. Like
+  /// [`Self::new`], the copy is not committed until [`Self::commit`].
+  pub fn fork(&self, conn: Connection) -> Result<Store, StoreError> {
+    Store::import(conn, self.constraints.clone(), &self.export()?)
+  }
+
+  /// Returns a self-contained snapshot of all data, including pending
+  /// modifications, for backup or transfer (see [`Workspace::sync_snapshot`]).
+  pub fn export(&self) -> Result<Box<[u8]>, StoreError> {
+    let (txr, workspace) = self.as_ref()?;
+    workspace.sync_snapshot(txr)
+  }
+
+  /// Creates a new store on `conn` holding the data from a snapshot made by
+  /// [`Self::export`]. Like [`Self::fork`], the result has its own replica ID
+  /// and can be synced with the original, and is not committed until
+  /// [`Self::commit`].
+  pub fn import(conn: Connection, constraints: Constraints, snapshot: &[u8]) -> Result<Store, StoreError> {
+    let mut res = Store::new(conn, constraints)?;
+    let (txr, workspace) = res.as_mut()?;
+    workspace.sync_join(txr, snapshot)?;
+    workspace.barrier(txr)?;
+    Ok(res)
+  }
+
+  /// Syncs both ways with another store, e.g. one recovered from another
+  /// device, covering all workspaces added to both, and issues barriers on
+  /// both. Returns events for `self` and `other` respectively, as from
+  /// [`Self::barrier`]. Fails with [`StoreError::DuplicateReplica`] if a
+  /// workspace has the same replica ID in both.
+  ///
+  /// Nothing is committed. If this fails halfway, both are rolled back (see
+  /// [`Self::rollback`]), so commit anything to be kept beforehand.
+  #[allow(clippy::type_complexity)]
+  pub fn absorb(
+    &mut self,
+    other: &mut Store,
+  ) -> Result<(BTreeMap<String, Vec<CEventData>>, BTreeMap<String, Vec<CEventData>>), StoreError> {
+    for (name, workspace) in self.workspaces() {
+      if other.as_ref_named(name).is_ok_and(|(_, other)| other.this() == workspace.this()) {
+        return Err(StoreError::DuplicateReplica);
+      }
+    }
+    let res = (|| {
+      let actions = other.sync_actions(&self.sync_version()?)?;
+      let other_actions = self.sync_actions(&other.sync_version()?)?;
+      self.sync_join(&actions)?;
+      other.sync_join(&other_actions)?;
+      Ok((self.barrier()?, other.barrier()?))
+    })();
+    if res.is_err() {
+      let (rollback, other_rollback) = (self.rollback(), other.rollback());
+      rollback?;
+      other_rollback?;
+    }
+    res
+  }
+
+  /// Discards everything since the last commit, including saved changes and
+  /// pending modifications, and starts a new transaction. Undo and redo
+  /// history (see [`Workspace::undo`]) is cleared as well, as it may refer to
+  /// discarded changes.
+  pub fn rollback(&mut self) -> Result<(), StoreError> {
+    self.restart(if self.read_only { "ROLLBACK; BEGIN DEFERRED" } else { "ROLLBACK; BEGIN IMMEDIATE" })
+  }
+
+  fn restart(&mut self, sql: &str) -> Result<(), StoreError> {
+    let mut txr = self.txr.take().ok_or(StoreError::Disconnected)?;
+    txr.execute_batch(sql)?;
+    let workspace = if self.read_only {
+      Workspace::load("", self.constraints.clone(), &txr)?
+    } else {
+      Workspace::new("", self.constraints.clone(), &mut txr)?
+    };
+    let old = std::mem::replace(&mut self.workspace, workspace);
+    self.workspace.inherit(old);
+    for (name, named) in &mut self.named {
+      let workspace = if self.read_only {
+        Workspace::load(name.as_str(), self.constraints.clone(), &txr)?
+      } else {
+        Workspace::new(name.as_str(), self.constraints.clone(), &mut txr)?
+      };
+      let old = std::mem::replace(named, workspace);
+      named.inherit(old);
+    }
     self.txr = Some(txr);
     Ok(())
   }
@@ -50,6 +479,501 @@ impl Store {
   }
 }
 
+fn run_maintenance(conn: &Connection, vacuum: bool) -> Result<Maintenance, StoreError> {
+  let page_count = || conn.query_row("PRAGMA page_count", (), |row| row.get::<_, u64>(0));
+  let before = page_count()?;
+  conn.execute_batch("ANALYZE; PRAGMA optimize;")?;
+  conn.execute_batch(if vacuum { "VACUUM;" } else { "PRAGMA incremental_vacuum;" })?;
+  Ok(Maintenance { pages_reclaimed: before.saturating_sub(page_count()?) })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::workspace::metadata::WorkspaceMetadataTransactor;
+  use rand::Rng;
+  use rusqlite::OpenFlags;
+
+  #[test]
+  fn rollback_simple() {
+    let mut store = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let [node0, node1]: [u128; 2] = rand::thread_rng().gen();
+
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.barrier(txr).unwrap();
+    let version = ws.sync_version(txr);
+    store.commit().unwrap();
+
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node1, Some(0));
+    ws.barrier(txr).unwrap();
+    ws.set_node(txr, node0, None);
+    store.rollback().unwrap();
+
+    let (txr, ws) = store.as_mut().unwrap();
+    assert_eq!(ws.node(txr, node0), Some(0));
+    assert_eq!(ws.node(txr, node1), None);
+    assert_eq!(ws.sync_version(txr), version);
+  }
+
+  #[test]
+  fn export_import_simple() {
+    let mut store0 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let mut rng = rand::thread_rng();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let mut nodes = Vec::new();
+    for _ in 0..20 {
+      let node = rng.gen();
+      ws0.set_node(txr0, node, Some(0));
+      ws0.set_atom(txr0, rng.gen(), Some((node, 1, vec![rng.gen()].into()))).unwrap();
+      if let Some(&prev) = nodes.last() {
+        ws0.set_edge(txr0, rng.gen(), Some((prev, 2, node)));
+      }
+      nodes.push(node);
+    }
+    ws0.barrier(txr0).unwrap();
+    ws0.set_node(txr0, nodes[0], None);
+    ws0.barrier(txr0).unwrap();
+
+    let snapshot = store0.export().unwrap();
+    let mut store1 = Store::import(Connection::open_in_memory().unwrap(), Constraints::new(), &snapshot).unwrap();
+    assert_ne!(store0.replica_id(), store1.replica_id());
+    for name in ["nodes", "atoms", "edges"] {
+      assert_eq!(store0.structure_digest(name).unwrap(), store1.structure_digest(name).unwrap());
+    }
+    let (txr1, ws1) = store1.as_ref().unwrap();
+    assert_eq!(ws1.node(txr1, nodes[0]), None);
+    assert_eq!(ws1.node(txr1, nodes[1]), Some(0));
+
+    // Both sides keep changing, then converge.
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, nodes[1], Some(1));
+    ws0.barrier(txr0).unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    ws1.set_node(txr1, nodes[2], None);
+    ws1.barrier(txr1).unwrap();
+    store0.absorb(&mut store1).unwrap();
+    for name in ["nodes", "atoms", "edges"] {
+      assert_eq!(store0.structure_digest(name).unwrap(), store1.structure_digest(name).unwrap());
+    }
+    let (txr1, ws1) = store1.as_ref().unwrap();
+    assert_eq!(ws1.node(txr1, nodes[1]), Some(1));
+    assert_eq!(ws1.node(txr1, nodes[2]), None);
+  }
+
+  #[test]
+  fn fork_simple() {
+    let mut store0 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let [node0, node1, node2, atom0]: [u128; 4] = rand::thread_rng().gen();
+    let (txr, ws) = store0.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.set_atom(txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.barrier(txr).unwrap();
+    ws.set_node(txr, node1, Some(0));
+
+    let mut store1 = store0.fork(Connection::open_in_memory().unwrap()).unwrap();
+    assert_ne!(store0.replica_id(), store1.replica_id());
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    assert_eq!(ws1.atom(txr1, atom0).unwrap(), Some((node0, 1, vec![0].into())));
+    assert_eq!(ws1.node(txr1, node1), Some(0));
+    ws1.set_atom(txr1, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws1.barrier(txr1).unwrap();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node2, Some(0));
+    ws0.barrier(txr0).unwrap();
+
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    ws1.sync_join(txr1, &ws0.sync_actions(txr0, &ws1.sync_version(txr1)).unwrap()).unwrap();
+    ws1.barrier(txr1).unwrap();
+    ws0.sync_join(txr0, &ws1.sync_actions(txr1, &ws0.sync_version(txr0)).unwrap()).unwrap();
+    ws0.barrier(txr0).unwrap();
+    for (ws, txr) in [(&*ws0, &*txr0), (&*ws1, &*txr1)] {
+      assert_eq!(ws.atom(txr, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+      assert_eq!(ws.nodes(txr, &[node0, node1, node2]).len(), 3);
+    }
+    assert_eq!(ws0.sync_version(txr0), ws1.sync_version(txr1));
+  }
+
+  #[test]
+  fn absorb_simple() {
+    let mut store0 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let mut store1 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let [node0, node1]: [u128; 2] = rand::thread_rng().gen();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node0, Some(0));
+    ws0.barrier(txr0).unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    ws1.set_node(txr1, node1, Some(1));
+
+    let (events0, events1) = store0.absorb(&mut store1).unwrap();
+    assert_eq!((events0[""].len(), events1[""].len()), (1, 2));
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    assert_eq!(ws0.nodes(txr0, &[node0, node1]), ws1.nodes(txr1, &[node0, node1]));
+    assert_eq!(ws0.sync_version(txr0), ws1.sync_version(txr1));
+    let (events0, events1) = store1.absorb(&mut store0).unwrap();
+    assert!(events0[""].is_empty() && events1[""].is_empty());
+
+    // Same replica ID on both sides.
+    let mut txr2: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    txr2.init_this("");
+    txr2.put_this("", store0.replica_id());
+    let mut store2 = Store::new(txr2.try_into().unwrap(), Constraints::new()).unwrap();
+    assert!(matches!(store0.absorb(&mut store2), Err(StoreError::DuplicateReplica)));
+  }
+
+  #[test]
+  fn absorb_failure() {
+    let mut store0 = Store::in_memory(Constraints::new()).unwrap();
+    let mut store1 = Store::in_memory(Constraints::new()).unwrap();
+    let [node0, node1, atom0]: [u128; 3] = rand::thread_rng().gen();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node0, Some(0));
+    ws0.set_atom(txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(txr0).unwrap();
+    store0.commit().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    ws1.set_node(txr1, node1, Some(1));
+    ws1.barrier(txr1).unwrap();
+    store1.commit().unwrap();
+    let versions = (store0.sync_version().unwrap(), store1.sync_version().unwrap());
+
+    // Fails joining atoms into `store1`, after joining into `store0`.
+    let (txr1, _) = store1.as_mut().unwrap();
+    txr1.execute_batch("DROP TABLE \".atoms.data\"").unwrap();
+    assert!(matches!(store0.absorb(&mut store1), Err(StoreError::Sqlite(_))));
+    assert_eq!((store0.sync_version().unwrap(), store1.sync_version().unwrap()), versions);
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    assert_eq!(ws0.barrier(txr0).unwrap().len(), 0);
+    assert_eq!(ws0.node(txr0, node1), None);
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    assert_eq!(ws1.barrier(txr1).unwrap().len(), 0);
+    assert_eq!(ws1.node(txr1, node0), None);
+
+    store0.absorb(&mut store1).unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    assert_eq!(ws1.atom(txr1, atom0).unwrap(), Some((node0, 1, vec![0].into())));
+  }
+
+  #[test]
+  fn named_workspaces() {
+    let mut store0 = Store::in_memory(Constraints::new()).unwrap();
+    let mut store1 = Store::in_memory(Constraints::new()).unwrap();
+    let [node0, node1, node2]: [u128; 3] = rand::thread_rng().gen();
+    assert!(matches!(store0.as_ref_named("extra"), Err(StoreError::UnknownWorkspace(name)) if name == "extra"));
+    for store in [&mut store0, &mut store1] {
+      store.add_workspace("extra").unwrap();
+      store.add_workspace("extra").unwrap();
+    }
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node0, Some(0));
+    let (txr0, ws0) = store0.as_mut_named("extra").unwrap();
+    ws0.set_node(txr0, node1, Some(1));
+    let (txr1, ws1) = store1.as_mut_named("extra").unwrap();
+    ws1.set_node(txr1, node2, Some(2));
+    let events = store0.barrier().unwrap();
+    assert_eq!((events[""].len(), events["extra"].len()), (1, 1));
+    store1.barrier().unwrap();
+
+    // Both workspaces are synced together, but kept apart.
+    let stats = store1.sync_join(&store0.sync_actions(&store1.sync_version().unwrap()).unwrap()).unwrap();
+    assert_eq!(stats.applied_new, 2);
+    store0.sync_join(&store1.sync_actions(&store0.sync_version().unwrap()).unwrap()).unwrap();
+    store0.barrier().unwrap();
+    store1.barrier().unwrap();
+    assert_eq!(store0.sync_version().unwrap(), store1.sync_version().unwrap());
+    let (txr1, ws1) = store1.as_ref_named("").unwrap();
+    assert_eq!(ws1.nodes(txr1, &[node0, node1, node2]).into_keys().collect::<Vec<_>>(), [node0]);
+    let (txr1, ws1) = store1.as_ref_named("extra").unwrap();
+    assert_eq!(ws1.node(txr1, node1), Some(1));
+
+    // Actions for workspaces missing on one side are ignored there.
+    let mut store2 = Store::in_memory(Constraints::new()).unwrap();
+    store2.sync_join(&store0.sync_actions(&store2.sync_version().unwrap()).unwrap()).unwrap();
+    let (events2, events0) = store2.absorb(&mut store0).unwrap();
+    assert_eq!((events2.len(), events2[""].len(), events0[""].len()), (1, 1, 0));
+
+    // Added workspaces are kept on rollback.
+    store1.commit().unwrap();
+    store1.rollback().unwrap();
+    let (txr1, ws1) = store1.as_ref_named("extra").unwrap();
+    assert_eq!(ws1.node(txr1, node2), Some(2));
+  }
+
+  #[test]
+  fn resync_after_rollback() {
+    let mut store0 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let mut store1 = Store::new(Connection::open_in_memory().unwrap(), Constraints::new()).unwrap();
+    let [node0, node1, node2]: [u128; 3] = rand::thread_rng().gen();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node0, Some(0));
+    ws0.barrier(txr0).unwrap();
+    store0.commit().unwrap();
+
+    // Writes reach the peer, but are lost here, as in a crash before commit.
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node1, Some(0));
+    ws0.barrier(txr0).unwrap();
+    store1.absorb(&mut store0).unwrap();
+    store1.commit().unwrap();
+    store0.rollback().unwrap();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    assert_eq!(ws0.node(txr0, node1), None);
+
+    // Syncing back restores them, and later local writes still go through.
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    let actions = ws1.sync_actions(txr1, &ws0.sync_version(txr0)).unwrap();
+    assert_eq!(ws0.sync_join(txr0, &actions).unwrap().unknown_own, 1);
+    ws0.barrier(txr0).unwrap();
+    assert_eq!(ws0.node(txr0, node1), Some(0));
+    ws0.set_node(txr0, node2, Some(0));
+    ws0.barrier(txr0).unwrap();
+    store1.absorb(&mut store0).unwrap();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    assert_eq!(ws1.nodes(txr1, &[node0, node1, node2]).len(), 3);
+    assert_eq!(ws0.sync_version(txr0), ws1.sync_version(txr1));
+  }
+
+  #[test]
+  fn read_only_simple() {
+    let path = std::env::temp_dir().join(format!("dust-{:x}.db", rand::thread_rng().gen::<u64>()));
+    let [node0, node1]: [u128; 2] = rand::thread_rng().gen();
+    let conn = Connection::open(&path).unwrap();
+    assert!(matches!(Store::open_read_only(conn, Constraints::new()), Err(StoreError::Uninitialised)));
+
+    let conn = Connection::open(&path).unwrap();
+    Pragmas::default().apply(&conn).unwrap();
+    let mut store = Store::new(conn, Constraints::new()).unwrap();
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.barrier(txr).unwrap();
+    store.commit().unwrap();
+
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+    let mut reader = Store::open_read_only(conn, Constraints::new()).unwrap();
+    assert_eq!(reader.replica_id(), store.replica_id());
+    let (txr, ws) = reader.as_ref().unwrap();
+    assert_eq!(ws.node(txr, node0), Some(0));
+    let err = txr.execute_batch("DELETE FROM \".nodes.data\"").unwrap_err();
+    assert_eq!(err.sqlite_error_code(), Some(rusqlite::ErrorCode::ReadOnly));
+    assert!(matches!(reader.as_mut(), Err(StoreError::ReadOnly)));
+
+    // New snapshot on commit.
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node1, Some(0));
+    ws.barrier(txr).unwrap();
+    store.commit().unwrap();
+    let (txr, ws) = reader.as_ref().unwrap();
+    assert_eq!(ws.node(txr, node1), None);
+    reader.commit().unwrap();
+    let (txr, ws) = reader.as_ref().unwrap();
+    assert_eq!(ws.node(txr, node1), Some(0));
+    reader.close().unwrap();
+    store.close().unwrap();
+
+    for suffix in ["", "-wal", "-shm"] {
+      let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+  }
+
+  #[test]
+  fn read_only_without_indices() {
+    let path = std::env::temp_dir().join(format!("dust-{:x}.db", rand::thread_rng().gen::<u64>()));
+    let [node0, node1, edge0]: [u128; 3] = rand::thread_rng().gen();
+    let mut store = Store::new(Connection::open(&path).unwrap(), Constraints::new()).unwrap();
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.set_node(txr, node1, Some(0));
+    ws.set_edge(txr, edge0, Some((node0, 1, node1)));
+    ws.barrier(txr).unwrap();
+    let store_digest = ws.structure_digest(txr, "edges").unwrap();
+    // As created before the label index on edges and digests were added.
+    txr.execute_batch("DROP INDEX \".edges.data.idx_label\";").unwrap();
+    for name in ["nodes", "atoms", "edges"] {
+      txr.execute_batch(&format!("DROP TABLE \".{name}.digest\";")).unwrap();
+    }
+    store.close().unwrap();
+
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+    let mut reader = Store::open_read_only(conn, Constraints::new()).unwrap();
+    let (txr, ws) = reader.as_ref().unwrap();
+    assert_eq!(ws.edge_id_src_dst_by_label(txr, 1).into_iter().collect::<Vec<_>>(), [(edge0, (node0, node1))]);
+    reader.rollback().unwrap();
+    let (txr, ws) = reader.as_ref().unwrap();
+    assert_eq!(ws.edge(txr, edge0), Some((node0, 1, node1)));
+    assert_eq!(reader.structure_digest("edges").unwrap(), store_digest);
+    reader.close().unwrap();
+
+    for suffix in ["", "-wal", "-shm"] {
+      let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+  }
+
+  #[test]
+  fn retry_simple() {
+    let path = std::env::temp_dir().join(format!("dust-{:x}.db", rand::thread_rng().gen::<u64>()));
+    let open = || {
+      let conn = Connection::open(&path).unwrap();
+      conn.busy_timeout(Duration::ZERO).unwrap();
+      conn
+    };
+    let retry = Retry { attempts: 3, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(2) };
+    let store = Store::new_with_retry(open(), Constraints::new(), retry.clone()).unwrap();
+
+    // Gives up while the lock is held.
+    let err = Store::new_with_retry(open(), Constraints::new(), retry).err().unwrap();
+    assert!(matches!(err, StoreError::Sqlite(err) if err.sqlite_error_code() == Some(ErrorCode::DatabaseBusy)));
+
+    // Succeeds once it is released.
+    let retry =
+      Retry { attempts: 100, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(10) };
+    let thread = std::thread::spawn(move || {
+      std::thread::sleep(Duration::from_millis(50));
+      store.close().unwrap();
+    });
+    let store = Store::new_with_retry(open(), Constraints::new(), retry).unwrap();
+    thread.join().unwrap();
+    store.close().unwrap();
+
+    for suffix in ["", "-wal", "-shm"] {
+      let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+  }
+
+  #[cfg(feature = "sqlcipher")]
+  #[test]
+  fn key_simple() {
+    let path = std::env::temp_dir().join(format!("dust-{:x}.db", rand::thread_rng().gen::<u64>()));
+    let node0: u128 = rand::thread_rng().gen();
+    let pragmas = Pragmas { key: Some("correct".to_owned()), ..Default::default() };
+    let conn = Connection::open(&path).unwrap();
+    pragmas.apply(&conn).unwrap();
+    let mut store = Store::new(conn, Constraints::new()).unwrap();
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.barrier(txr).unwrap();
+    store.close().unwrap();
+
+    for key in [Some("wrong"), None] {
+      let conn = Connection::open(&path).unwrap();
+      let err = Pragmas { key: key.map(Into::into), ..Default::default() }.apply(&conn).err().unwrap();
+      assert!(matches!(err, StoreError::Sqlite(err) if err.sqlite_error_code() == Some(ErrorCode::NotADatabase)));
+    }
+    let conn = Connection::open(&path).unwrap();
+    pragmas.apply(&conn).unwrap();
+    let mut store = Store::new(conn, Constraints::new()).unwrap();
+    let (txr, ws) = store.as_mut().unwrap();
+    assert_eq!(ws.node(txr, node0), Some(0));
+    store.close().unwrap();
+
+    for suffix in ["", "-wal", "-shm"] {
+      let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+  }
+
+  #[test]
+  fn pragmas_simple() {
+    let path = std::env::temp_dir().join(format!("dust-{:x}.db", rand::thread_rng().gen::<u64>()));
+    let pragmas = Pragmas { synchronous: Synchronous::Full, ..Default::default() };
+    for _ in 0..2 {
+      let conn = Connection::open(&path).unwrap();
+      pragmas.apply(&conn).unwrap();
+      let journal_mode: String = conn.query_row("PRAGMA journal_mode", (), |row| row.get(0)).unwrap();
+      let synchronous: u64 = conn.query_row("PRAGMA synchronous", (), |row| row.get(0)).unwrap();
+      assert_eq!(journal_mode, "wal");
+      assert_eq!(synchronous, 2);
+      let mut store = Store::new(conn, Constraints::new()).unwrap();
+      store.commit().unwrap();
+      store.close().unwrap();
+    }
+    for suffix in ["", "-wal", "-shm"] {
+      let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+  }
+
+  #[test]
+  fn maintain_simple() {
+    let mut store = Store::in_memory(Constraints::new()).unwrap();
+    let mut rng = rand::thread_rng();
+    let node: u128 = rng.gen();
+    let atoms = (0..100).map(|_| rng.gen()).collect::<Vec<u128>>();
+    for vacuum in [false, true] {
+      let (txr, ws) = store.as_mut().unwrap();
+      ws.set_node(txr, node, Some(0));
+      for &atom in &atoms {
+        ws.set_atom(txr, atom, Some((node, 1, vec![0; 4096].into()))).unwrap();
+      }
+      ws.barrier(txr).unwrap();
+      store.commit().unwrap();
+      let (txr, ws) = store.as_mut().unwrap();
+      ws.set_node(txr, node, None);
+      ws.barrier(txr).unwrap();
+      assert!(store.maintain(vacuum).unwrap().pages_reclaimed > 0);
+    }
+
+    // Still usable, with pending modifications kept.
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node, Some(1));
+    store.maintain(false).unwrap();
+    let (txr, ws) = store.as_mut().unwrap();
+    assert_eq!(ws.barrier(txr).unwrap().len(), 1);
+    assert_eq!(ws.node(txr, node), Some(1));
+  }
+
+  #[test]
+  fn check_integrity_simple() {
+    let mut store = Store::in_memory(Constraints::new()).unwrap();
+    let [node0, node1, atom0, edge0]: [u128; 4] = rand::thread_rng().gen();
+    let (txr, ws) = store.as_mut().unwrap();
+    ws.set_node(txr, node0, Some(0));
+    ws.set_node(txr, node1, Some(0));
+    ws.set_atom(txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_edge(txr, edge0, Some((node1, 2, node0)));
+    ws.barrier(txr).unwrap();
+    assert!(store.check_integrity().unwrap().is_ok());
+
+    // Written behind the library's back.
+    let (txr, _) = store.as_mut().unwrap();
+    txr.execute("DELETE FROM \".nodes.data\" WHERE id = ?", (node0.to_be_bytes(),)).unwrap();
+    txr.execute("UPDATE \".edges.data\" SET clock = ?", (u64::MAX.to_be_bytes(),)).unwrap();
+    let report = store.check_integrity().unwrap();
+    assert!(report.sqlite.is_empty());
+    let bucket = store.replica_id();
+    assert_eq!(
+      report.anomalies[..2],
+      [Anomaly::AtomWithoutNode { atom: atom0, src: node0 }, Anomaly::EdgeWithoutNode { edge: edge0, node: node0 }]
+    );
+    assert_eq!(report.anomalies.len(), 3);
+    assert!(
+      matches!(report.anomalies[2], Anomaly::ClockBehind { name: "edges", bucket: b, found: u64::MAX, .. } if b == bucket)
+    );
+  }
+
+  #[test]
+  fn in_memory_simple() {
+    let mut store0 = Store::in_memory(Constraints::new()).unwrap();
+    let mut store1 = Store::in_memory(Constraints::new()).unwrap();
+    assert_ne!(store0.replica_id(), store1.replica_id());
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    ws0.set_node(txr0, node0, Some(0));
+    ws0.set_atom(txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(txr0).unwrap();
+    store0.commit().unwrap();
+
+    let (txr0, ws0) = store0.as_mut().unwrap();
+    let (txr1, ws1) = store1.as_mut().unwrap();
+    ws1.sync_join(txr1, &ws0.sync_actions(txr0, &ws1.sync_version(txr1)).unwrap()).unwrap();
+    ws1.barrier(txr1).unwrap();
+    assert_eq!(ws1.atom(txr1, atom0).unwrap(), Some((node0, 1, vec![0].into())));
+    store1.commit().unwrap();
+  }
+}
+
 /*
 /// Basic interface for model types.
 pub trait Model: Sized {
@@ -74,11 +998,11 @@ impl<T: Serialize + DeserializeOwned> AtomOption<T> {
     self.id
   }
   pub fn get(&self) -> Option<T> {
-    access_store_with(|store| store.atom(self.id).map(|(_, _, bytes)| deserialize(&bytes).unwrap()))
+    access_store_with(|store| store.atom(self.id).unwrap().map(|(_, _, bytes)| deserialize(&bytes).unwrap()))
   }
   pub fn set(&self, value: Option<&T>) {
     access_store_with(|store| {
-      store.set_atom(self.id, value.map(|inner| (self.src, self.label, serialize(inner).unwrap().into())))
+      store.set_atom(self.id, value.map(|inner| (self.src, self.label, serialize(inner).unwrap().into()))).unwrap()
     });
   }
 }
@@ -244,7 +1168,7 @@ mod tests {
 
       global::access_store_with(|store| {
         // Create `Trivial.unit`.
-        store.set_atom(rng.gen(), Some((id, Self::UNIT_LABEL, serialize(unit).unwrap().into())));
+        store.set_atom(rng.gen(), Some((id, Self::UNIT_LABEL, serialize(unit).unwrap().into()))).unwrap();
       });
 
       // Return.
@@ -255,10 +1179,10 @@ mod tests {
       global::access_store_with(|store| {
         // Delete all fields.
         for (atom, _) in store.atom_label_value_by_src(self.id) {
-          store.set_atom(atom, None);
+          store.set_atom(atom, None).unwrap();
         }
         for (edge, _) in store.edge_label_dst_by_src(self.id) {
-          store.set_atom(edge, None);
+          store.set_atom(edge, None).unwrap();
         }
       });
     }
@@ -317,11 +1241,11 @@ mod tests {
 
       global::access_store_with(|store| {
         // Create `Something.atom_one`.
-        store.set_atom(rng.gen(), Some((id, Self::ATOM_ONE_LABEL, serialize(atom_one).unwrap().into())));
+        store.set_atom(rng.gen(), Some((id, Self::ATOM_ONE_LABEL, serialize(atom_one).unwrap().into()))).unwrap();
 
         // Create `Something.atom_two`.
         if let Some(atom_two) = atom_two {
-          store.set_atom(rng.gen(), Some((id, Self::ATOM_TWO_LABEL, serialize(atom_two).unwrap().into())));
+          store.set_atom(rng.gen(), Some((id, Self::ATOM_TWO_LABEL, serialize(atom_two).unwrap().into()))).unwrap();
         }
 
         // Create `Something.link_one`.
@@ -341,10 +1265,10 @@ mod tests {
       global::access_store_with(|store| {
         // Delete all fields.
         for (atom, _) in store.atom_label_value_by_src(self.id) {
-          store.set_atom(atom, None);
+          store.set_atom(atom, None).unwrap();
         }
         for (edge, _) in store.edge_label_dst_by_src(self.id) {
-          store.set_atom(edge, None);
+          store.set_atom(edge, None).unwrap();
         }
       });
     }
@@ -404,11 +1328,11 @@ mod tests {
   #[test]
   fn object_store_simple() {
     global::init_in_memory();
-    global::access_store_with(|store| store.set_atom(0, Some((1, 2, vec![2, 3, 3].into()))));
-    global::access_store_with(|store| store.set_atom(1, Some((3, 4, vec![2, 3, 3, 3].into()))));
+    global::access_store_with(|store| store.set_atom(0, Some((1, 2, vec![2, 3, 3].into()))).unwrap());
+    global::access_store_with(|store| store.set_atom(1, Some((3, 4, vec![2, 3, 3, 3].into()))).unwrap());
     global::access_store_with(|store| store.set_edge(rand::thread_rng().gen(), Some((0, 23333, 1))));
-    assert_eq!(global::access_store_with(|store| store.atom(0)), Some((1, 2, vec![2, 3, 3].into())));
-    assert_eq!(global::access_store_with(|store| store.atom(1)), Some((3, 4, vec![2, 3, 3, 3].into())));
+    assert_eq!(global::access_store_with(|store| store.atom(0).unwrap()), Some((1, 2, vec![2, 3, 3].into())));
+    assert_eq!(global::access_store_with(|store| store.atom(1).unwrap()), Some((3, 4, vec![2, 3, 3, 3].into())));
     let edges = global::access_store_with(|store| store.edge_label_dst_by_src(0));
     assert_eq!(edges.len(), 1);
     assert_eq!(edges[0].1, (23333, 1));
@@ -16,18 +16,177 @@
 
 pub mod atom_set;
 pub mod edge_set;
+#[cfg(any(test, feature = "test-util"))]
+pub mod memory;
 pub mod metadata;
 pub mod node_set;
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+  cell::Cell,
+  collections::{BTreeMap, BTreeSet, VecDeque},
+  ops::AddAssign,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use self::{atom_set::AtomSet, edge_set::EdgeSet, metadata::WorkspaceMetadata, node_set::NodeSet};
-use crate::{deserialize, ffi::structs::CEventData, serialize, Transactor};
+use self::{
+  atom_set::AtomSet,
+  edge_set::EdgeSet,
+  metadata::{system_clock, Clock, WorkspaceMetadata},
+  node_set::NodeSet,
+};
+use crate::{deserialize, ffi::structs::CEventData, frame, serialize, unframe, DecodeError, StoreError, Transactor};
 
 pub const NODES_NAME: &str = "nodes";
 pub const ATOMS_NAME: &str = "atoms";
 pub const EDGES_NAME: &str = "edges";
 
+/// Maximum number of steps kept for [`Workspace::undo`].
+pub const MAX_UNDO: usize = 100;
+
+/// Maximum number of parameters bound to a single statement, kept within
+/// SQLite's historical default limit of 999.
+pub const MAX_PARAMS: usize = 500;
+
+/// Result of joining a single item into a last-writer-wins set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+  /// There was no current item, and the incoming one is added.
+  Inserted,
+  /// The incoming item is newer and replaces the current one.
+  Applied,
+  /// The current item is newer, or the clock value has already been seen.
+  RejectedOlder,
+  /// The current item has the same `(bucket, clock)` as the incoming one.
+  RejectedTie,
+}
+
+/// An action not applied by [`Workspace::sync_join`] (see [`SyncStats::ignored`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ignored {
+  /// The structure, e.g. [`NODES_NAME`].
+  pub name: &'static str,
+  pub id: u128,
+  pub bucket: u64,
+  pub clock: u64,
+  /// Either [`Outcome::RejectedOlder`] or [`Outcome::RejectedTie`].
+  pub outcome: Outcome,
+}
+
+/// Counts of actions by [`Outcome`] in a [`Workspace::sync_join`] call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStats {
+  /// Actions for previously unknown ids.
+  pub applied_new: u64,
+  /// Actions replacing an older item.
+  pub applied_override: u64,
+  /// Actions no newer than what is already known, including duplicates. A
+  /// high proportion suggests the peer is sending data which was already seen.
+  pub ignored_older: u64,
+  /// Actions under [`Workspace::this`] newer than any recorded here, which are
+  /// joined like any other, moving local clocks past them. This is expected
+  /// after a crash or restoring a backup, when peers hold writes lost here.
+  /// Otherwise, another replica may be using the same ID (see
+  /// [`Workspace::set_reject_unknown_own`]).
+  pub unknown_own: u64,
+  /// The actions counted in [`Self::ignored_older`], in the order joined,
+  /// e.g. to log or report what a peer sent that was not applied.
+  pub ignored: Vec<Ignored>,
+}
+
+impl AddAssign for SyncStats {
+  fn add_assign(&mut self, mut rhs: Self) {
+    self.applied_new += rhs.applied_new;
+    self.applied_override += rhs.applied_override;
+    self.ignored_older += rhs.ignored_older;
+    self.unknown_own += rhs.unknown_own;
+    self.ignored.append(&mut rhs.ignored);
+  }
+}
+
+impl SyncStats {
+  /// Counts `outcomes` of joining actions with the given `(bucket, clock)`
+  /// values, in the same order, into structure `name`.
+  fn add(&mut self, name: &'static str, clocks: Vec<(u64, u64)>, outcomes: Vec<(u128, Outcome)>) {
+    for ((bucket, clock), (id, outcome)) in clocks.into_iter().zip(outcomes) {
+      match outcome {
+        Outcome::Inserted => self.applied_new += 1,
+        Outcome::Applied => self.applied_override += 1,
+        Outcome::RejectedOlder | Outcome::RejectedTie => {
+          self.ignored_older += 1;
+          self.ignored.push(Ignored { name, id, bucket, clock, outcome });
+        }
+      }
+    }
+  }
+}
+
+/// Returns the `(bucket, clock)` values of decoded actions, which
+/// [`SyncStats::add`] pairs with their outcomes.
+fn action_clocks<T>(actions: &[(u128, (u64, u64, T))]) -> Vec<(u64, u64)> {
+  actions.iter().map(|(_, (bucket, clock, _))| (*bucket, *clock)).collect()
+}
+
+/// An inconsistency found by [`Workspace::check_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+  /// An atom whose source node does not exist.
+  AtomWithoutNode { atom: u128, src: u128 },
+  /// An edge with an endpoint (`src` or `dst`) that does not exist.
+  EdgeWithoutNode { edge: u128, node: u128 },
+  /// Data in structure `name` with a clock value beyond what is recorded for
+  /// its bucket, which [`Workspace::recover`] repairs.
+  ClockBehind { name: &'static str, bucket: u64, recorded: Option<u64>, found: u64 },
+}
+
+/// Counters since the workspace was loaded (see [`Workspace::metrics`]),
+/// e.g. to monitor sync health over time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+  /// Local writes, including those made by barriers to maintain constraints.
+  pub writes: u64,
+  /// Incoming actions that were applied (see [`SyncStats`]).
+  pub actions_applied: u64,
+  /// Incoming actions that were ignored as no newer than what is present.
+  pub actions_dropped_stale: u64,
+  /// Total size of actions passed to [`Workspace::sync_join`].
+  pub bytes_synced_in: u64,
+  /// Total size of actions returned by the `sync_actions` methods.
+  pub bytes_synced_out: u64,
+  /// Events returned by barriers.
+  pub events_emitted: u64,
+}
+
+/// A single modification for [`Workspace::apply_batch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+  Node(u128, Option<u64>),
+  Atom(u128, Option<(u128, u64, Box<[u8]>)>),
+  Edge(u128, Option<(u128, u64, u128)>),
+}
+
+/// An incoming action dropped by [`Workspace::sync_join_with`], together with
+/// the item it lost to (if any). Both are `(bucket, clock, value)`, where the
+/// bucket identifies the client which made the write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+  Node {
+    id: u128,
+    dropped: (u64, u64, Option<u64>),
+    winning: Option<(u64, u64, Option<u64>)>,
+  },
+  Atom {
+    id: u128,
+    dropped: (u64, u64, Option<(u128, u64, Box<[u8]>)>),
+    winning: Option<(u64, u64, Option<(u128, u64, Box<[u8]>)>)>,
+  },
+  Edge {
+    id: u128,
+    dropped: (u64, u64, Option<(u128, u64, u128)>),
+    winning: Option<(u64, u64, Option<(u128, u64, u128)>)>,
+  },
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Constraints {
   sticky_nodes: BTreeSet<u64>,
@@ -61,44 +220,251 @@ pub struct Workspace {
   nodes: NodeSet,
   atoms: AtomSet,
   edges: EdgeSet,
+  undo: VecDeque<Step>,
+  redo: Vec<Step>,
+  debounce: BTreeMap<u64, Duration>,
+  held: BTreeMap<u128, Instant>, // Pending atoms to be kept pending until then
+  clock: Clock,
+  reject_unknown_own: bool,
+  metrics: Cell<Metrics>,
+}
+
+/// Local writes saved by one barrier, as `(id, prev, curr)` for each item.
+#[derive(Debug, Default)]
+struct Step {
+  nodes: Vec<(u128, Option<u64>, Option<u64>)>,
+  atoms: Vec<(u128, Option<(u128, u64, Box<[u8]>)>, Option<(u128, u64, Box<[u8]>)>)>,
+  edges: Vec<(u128, Option<(u128, u64, u128)>, Option<(u128, u64, u128)>)>,
+}
+
+impl Step {
+  fn is_empty(&self) -> bool {
+    self.nodes.is_empty() && self.atoms.is_empty() && self.edges.is_empty()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Replay {
+  Undo,
+  Redo,
 }
 
 impl Workspace {
-  pub fn new(prefix: &'static str, constraints: Constraints, txr: &mut Transactor) -> Self {
-    let metadata = WorkspaceMetadata::new(prefix, txr);
-    let nodes = NodeSet::new(prefix, NODES_NAME, txr);
-    let atoms = AtomSet::new(prefix, ATOMS_NAME, txr);
+  pub fn new(prefix: impl Into<Arc<str>>, constraints: Constraints, txr: &mut Transactor) -> Result<Self, StoreError> {
+    let prefix = prefix.into();
+    let metadata = WorkspaceMetadata::new(prefix.clone(), txr);
+    let nodes = NodeSet::new(prefix.clone(), NODES_NAME, txr);
+    let atoms = AtomSet::new(prefix.clone(), ATOMS_NAME, txr)?;
     let edges = EdgeSet::new(prefix, EDGES_NAME, txr);
-    Self { metadata, constraints, nodes, atoms, edges }
+    Ok(Self::from_parts(metadata, constraints, nodes, atoms, edges))
+  }
+
+  /// Loads an existing workspace without creating or altering any tables, so
+  /// that it works on read-only connections and on databases created before
+  /// newer indices were added. Fails with [`StoreError::Uninitialised`] if
+  /// the workspace was never created.
+  pub fn load(prefix: impl Into<Arc<str>>, constraints: Constraints, txr: &Transactor) -> Result<Self, StoreError> {
+    let prefix = prefix.into();
+    let table = format!("{prefix}.this");
+    let exists: u64 =
+      txr.query_row("SELECT COUNT(*) FROM sqlite_schema WHERE name = ?", (&table,), |row| row.get(0))?;
+    if exists == 0 {
+      return Err(StoreError::Uninitialised);
+    }
+    let metadata = WorkspaceMetadata::load(prefix.clone(), txr).ok_or(StoreError::Uninitialised)?;
+    let nodes = NodeSet::load(prefix.clone(), NODES_NAME, txr);
+    let atoms = AtomSet::load(prefix.clone(), ATOMS_NAME, txr)?;
+    let edges = EdgeSet::load(prefix, EDGES_NAME, txr);
+    Ok(Self::from_parts(metadata, constraints, nodes, atoms, edges))
+  }
+
+  fn from_parts(
+    metadata: WorkspaceMetadata,
+    constraints: Constraints,
+    nodes: NodeSet,
+    atoms: AtomSet,
+    edges: EdgeSet,
+  ) -> Self {
+    let (undo, redo, debounce, held) = (VecDeque::new(), Vec::new(), BTreeMap::new(), BTreeMap::new());
+    let (clock, reject_unknown_own, metrics) = (system_clock, false, Cell::default());
+    Self { metadata, constraints, nodes, atoms, edges, undo, redo, debounce, held, clock, reject_unknown_own, metrics }
+  }
+
+  /// Returns a snapshot of the counters.
+  pub fn metrics(&self) -> Metrics {
+    self.metrics.get()
+  }
+
+  fn count(&self, f: impl FnOnce(&mut Metrics)) {
+    let mut metrics = self.metrics.get();
+    f(&mut metrics);
+    self.metrics.set(metrics);
+  }
+
+  fn count_join(&self, len: usize, stats: &SyncStats) {
+    self.count(|metrics| {
+      metrics.bytes_synced_in += len as u64;
+      metrics.actions_applied += stats.applied_new + stats.applied_override;
+      metrics.actions_dropped_stale += stats.ignored_older;
+    });
+  }
+
+  /// Returns this client's ID, used as the bucket for local writes.
+  pub fn this(&self) -> u64 {
+    self.metadata.this()
+  }
+
+  /// Keeps local writes to atoms with `label` pending through barriers until
+  /// none has been made to the same atom for `window`, so that rapid
+  /// successive writes (e.g. on every keystroke) are saved, reported and
+  /// synced as one. `None` turns this off for writes made afterwards.
+  ///
+  /// Held writes are visible to reads like any other pending modification,
+  /// and are lost in the same way if not saved, so call [`Self::flush`]
+  /// before committing where they must be durable.
+  pub fn set_debounce(&mut self, label: u64, window: Option<Duration>) {
+    match window {
+      Some(window) => self.debounce.insert(label, window),
+      None => self.debounce.remove(&label),
+    };
+  }
+
+  /// Sets the source of clock values for local writes, e.g. to use a hybrid
+  /// logical clock (see [`Clock`]). Defaults to [`system_clock`]; existing
+  /// data stays valid whichever is used.
+  pub fn set_clock(&mut self, clock: Clock) {
+    self.clock = clock;
+  }
+
+  /// Makes [`Self::sync_join`] and [`Self::sync_join_with`] fail with
+  /// [`StoreError::DuplicateReplica`], joining nothing, if any incoming action
+  /// would count towards [`SyncStats::unknown_own`]. This catches replicas
+  /// sharing an ID before their writes get mixed up, but also rejects writes
+  /// lost here in a crash, so it has to be turned off again to resync those.
+  pub fn set_reject_unknown_own(&mut self, reject: bool) {
+    self.reject_unknown_own = reject;
+  }
+
+  /// Takes settings over from a previous instance of the same workspace, e.g.
+  /// after reloading it on rollback.
+  pub(crate) fn inherit(&mut self, old: Workspace) {
+    self.debounce = old.debounce;
+    self.clock = old.clock;
+    self.reject_unknown_own = old.reject_unknown_own;
   }
 
   pub fn node(&self, txr: &Transactor, id: u128) -> Option<u64> {
     self.nodes.get(txr, id).and_then(|(_, _, label)| label)
   }
+  /// Returns the `(bucket, clock)` of the latest write to a node, including
+  /// removals. The bucket identifies the client which made the write.
+  pub fn node_clock(&self, txr: &Transactor, id: u128) -> Option<(u64, u64)> {
+    self.nodes.get(txr, id).map(|(bucket, clock, _)| (bucket, clock))
+  }
+  pub fn nodes(&self, txr: &Transactor, ids: &[u128]) -> BTreeMap<u128, u64> {
+    let items = self.nodes.get_many(txr, ids);
+    items.into_iter().filter_map(|(id, (_, _, label))| Some((id, label?))).collect()
+  }
   pub fn node_id_by_label(&self, txr: &Transactor, label: u64) -> BTreeMap<u128, ()> {
     self.nodes.id_by_label(txr, label)
   }
-  pub fn atom(&self, txr: &Transactor, id: u128) -> Option<(u128, u64, Box<[u8]>)> {
-    self.atoms.get(txr, id).and_then(|(_, _, slv)| slv)
+  pub fn atom(&self, txr: &Transactor, id: u128) -> Result<Option<(u128, u64, Box<[u8]>)>, StoreError> {
+    Ok(self.atoms.get(txr, id)?.and_then(|(_, _, slv)| slv))
+  }
+  /// Returns an atom holding a big-endian `u64` (see [`Self::set_atom_u64`]).
+  pub fn atom_u64(&self, txr: &Transactor, id: u128) -> Result<Option<(u128, u64, u64)>, StoreError> {
+    let Some((src, label, value)) = self.atom(txr, id)? else { return Ok(None) };
+    let bytes =
+      <[u8; 8]>::try_from(&*value).map_err(|_| DecodeError::InvalidLength { expected: 8, found: value.len() })?;
+    Ok(Some((src, label, u64::from_be_bytes(bytes))))
+  }
+  /// Returns an atom holding a UTF-8 string (see [`Self::set_atom_str`]).
+  pub fn atom_str(&self, txr: &Transactor, id: u128) -> Result<Option<(u128, u64, String)>, StoreError> {
+    let Some((src, label, value)) = self.atom(txr, id)? else { return Ok(None) };
+    let string = String::from_utf8(value.into_vec()).map_err(|_| DecodeError::InvalidUtf8)?;
+    Ok(Some((src, label, string)))
   }
-  pub fn atom_id_label_value_by_src(&self, txr: &Transactor, src: u128) -> BTreeMap<u128, (u64, Box<[u8]>)> {
-    self.atoms.id_label_value_by_src(txr, src)
+  /// Returns an atom holding a single `0` or `1` byte (see [`Self::set_atom_bool`]).
+  pub fn atom_bool(&self, txr: &Transactor, id: u128) -> Result<Option<(u128, u64, bool)>, StoreError> {
+    let Some((src, label, value)) = self.atom(txr, id)? else { return Ok(None) };
+    let boolean = match *value {
+      [0] => false,
+      [1] => true,
+      [byte] => return Err(DecodeError::InvalidBool(byte).into()),
+      _ => return Err(DecodeError::InvalidLength { expected: 1, found: value.len() }.into()),
+    };
+    Ok(Some((src, label, boolean)))
   }
-  pub fn atom_id_value_by_src_label(&self, txr: &Transactor, src: u128, label: u64) -> BTreeMap<u128, Box<[u8]>> {
-    self.atoms.id_value_by_src_label(txr, src, label)
+  /// Returns the `(bucket, clock)` of the latest write to an atom, including
+  /// removals. The bucket identifies the client which made the write.
+  pub fn atom_clock(&self, txr: &Transactor, id: u128) -> Result<Option<(u64, u64)>, StoreError> {
+    Ok(self.atoms.clock_of(txr, id)?)
   }
-  pub fn atom_id_src_value_by_label(&self, txr: &Transactor, label: u64) -> BTreeMap<u128, (u128, Box<[u8]>)> {
-    self.atoms.id_src_value_by_label(txr, label)
+  pub fn atoms(&self, txr: &Transactor, ids: &[u128]) -> Result<BTreeMap<u128, (u128, u64, Box<[u8]>)>, StoreError> {
+    let items = self.atoms.get_many(txr, ids)?;
+    Ok(items.into_iter().filter_map(|(id, (_, _, slv))| Some((id, slv?))).collect())
   }
-  pub fn atom_id_src_by_label_value(&self, txr: &Transactor, label: u64, value: &[u8]) -> BTreeMap<u128, u128> {
-    self.atoms.id_src_by_label_value(txr, label, value)
+  pub fn atom_id_label_value_by_src(
+    &self,
+    txr: &Transactor,
+    src: u128,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>, StoreError> {
+    Ok(self.atoms.id_label_value_by_src(txr, src)?)
+  }
+  pub fn atom_id_value_by_src_label(
+    &self,
+    txr: &Transactor,
+    src: u128,
+    label: u64,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>, StoreError> {
+    Ok(self.atoms.id_value_by_src_label(txr, src, label)?)
+  }
+  pub fn atom_id_src_value_by_label(
+    &self,
+    txr: &Transactor,
+    label: u64,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>, StoreError> {
+    Ok(self.atoms.id_src_value_by_label(txr, label)?)
+  }
+  pub fn atom_id_src_by_label_value(
+    &self,
+    txr: &Transactor,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>, StoreError> {
+    Ok(self.atoms.id_src_by_label_value(txr, label, value)?)
   }
   pub fn edge(&self, txr: &Transactor, id: u128) -> Option<(u128, u64, u128)> {
     self.edges.get(txr, id).and_then(|(_, _, sld)| sld)
   }
+  /// Returns the `(bucket, clock)` of the latest write to an edge, including
+  /// removals. The bucket identifies the client which made the write.
+  pub fn edge_clock(&self, txr: &Transactor, id: u128) -> Option<(u64, u64)> {
+    self.edges.get(txr, id).map(|(bucket, clock, _)| (bucket, clock))
+  }
+  pub fn edges(&self, txr: &Transactor, ids: &[u128]) -> BTreeMap<u128, (u128, u64, u128)> {
+    let items = self.edges.get_many(txr, ids);
+    items.into_iter().filter_map(|(id, (_, _, sld))| Some((id, sld?))).collect()
+  }
   pub fn edge_id_label_dst_by_src(&self, txr: &Transactor, src: u128) -> BTreeMap<u128, (u64, u128)> {
     self.edges.id_label_dst_by_src(txr, src)
   }
+  /// Returns all edges from `src` grouped by label, each group ordered by
+  /// edge id.
+  pub fn edge_label_id_dst_by_src(&self, txr: &Transactor, src: u128) -> BTreeMap<u64, Vec<(u128, u128)>> {
+    let mut res = BTreeMap::<u64, Vec<(u128, u128)>>::new();
+    for (id, (label, dst)) in self.edges.id_label_dst_by_src(txr, src) {
+      res.entry(label).or_default().push((id, dst));
+    }
+    res
+  }
+  /// Returns `(label, dst)` of all edges from `src` with any of `labels`,
+  /// ordered by label and then by `dst`.
+  pub fn edge_label_dst_by_src_labels(&self, txr: &Transactor, src: u128, labels: &[u64]) -> Vec<(u64, u128)> {
+    let mut res = self.edges.id_label_dst_by_src_labels(txr, src, labels).into_values().collect::<Vec<_>>();
+    res.sort();
+    res
+  }
   pub fn edge_id_dst_by_src_label(&self, txr: &Transactor, src: u128, label: u64) -> BTreeMap<u128, u128> {
     self.edges.id_dst_by_src_label(txr, src, label)
   }
@@ -108,23 +474,198 @@ impl Workspace {
   pub fn edge_id_src_by_dst_label(&self, txr: &Transactor, dst: u128, label: u64) -> BTreeMap<u128, u128> {
     self.edges.id_src_by_dst_label(txr, dst, label)
   }
+  pub fn edge_id_src_dst_by_label(&self, txr: &Transactor, label: u64) -> BTreeMap<u128, (u128, u128)> {
+    self.edges.id_src_dst_by_label(txr, label)
+  }
+
+  /// Walks outgoing edges with `label` breadth-first from `start`, returning
+  /// each reached node (including `start`) with its distance, in the order
+  /// reached. Nodes are visited once, so cycles terminate; nodes further than
+  /// `max_depth` edges away are left out.
+  pub fn traverse(&self, txr: &Transactor, start: u128, label: u64, max_depth: usize) -> Vec<(u128, usize)> {
+    let mut res = vec![(start, 0)];
+    let mut visited = BTreeSet::from([start]);
+    let mut i = 0;
+    while let Some(&(node, depth)) = res.get(i) {
+      i += 1;
+      if depth == max_depth {
+        continue;
+      }
+      for dst in self.edges.id_dst_by_src_label(txr, node, label).into_values() {
+        if visited.insert(dst) {
+          res.push((dst, depth + 1));
+        }
+      }
+    }
+    res
+  }
 
   pub fn set_node(&mut self, txr: &Transactor, id: u128, label: Option<u64>) {
     let this = self.metadata.this();
-    let next = self.nodes.next();
+    let next = self.nodes.next_with(self.clock);
     assert!(self.nodes.set(txr, id, this, next, label));
+    self.count(|metrics| metrics.writes += 1);
   }
 
-  pub fn set_atom(&mut self, txr: &Transactor, id: u128, slv: Option<(u128, u64, Box<[u8]>)>) {
+  pub fn set_atom(
+    &mut self,
+    txr: &Transactor,
+    id: u128,
+    slv: Option<(u128, u64, Box<[u8]>)>,
+  ) -> Result<(), StoreError> {
     let this = self.metadata.this();
-    let next = self.atoms.next();
-    assert!(self.atoms.set(txr, id, this, next, slv));
+    let next = self.atoms.next_with(self.clock);
+    let window = slv.as_ref().and_then(|(_, label, _)| self.debounce.get(label)).copied();
+    assert!(self.atoms.set(txr, id, this, next, slv)?);
+    match window {
+      Some(window) => self.held.insert(id, Instant::now() + window),
+      None => self.held.remove(&id),
+    };
+    self.count(|metrics| metrics.writes += 1);
+    Ok(())
   }
 
   pub fn set_edge(&mut self, txr: &Transactor, id: u128, sld: Option<(u128, u64, u128)>) {
     let this = self.metadata.this();
-    let next = self.edges.next();
+    let next = self.edges.next_with(self.clock);
     assert!(self.edges.set(txr, id, this, next, sld));
+    self.count(|metrics| metrics.writes += 1);
+  }
+
+  /// Sets atom only if it currently equals `expected`, with `None` meaning that
+  /// it does not exist. Returns whether it was set.
+  ///
+  /// As the transactor holds the write lock until committed, no other writer
+  /// can change the atom in between.
+  pub fn cas_atom(
+    &mut self,
+    txr: &Transactor,
+    id: u128,
+    expected: Option<(u128, u64, &[u8])>,
+    slv: Option<(u128, u64, Box<[u8]>)>,
+  ) -> Result<bool, StoreError> {
+    let curr = self.atom(txr, id)?;
+    if curr.as_ref().map(|(src, label, value)| (*src, *label, &**value)) != expected {
+      return Ok(false);
+    }
+    self.set_atom(txr, id, slv)?;
+    Ok(true)
+  }
+
+  /// Sets atom to a `u64`, encoded in big-endian like all other integers.
+  pub fn set_atom_u64(&mut self, txr: &Transactor, id: u128, slv: Option<(u128, u64, u64)>) -> Result<(), StoreError> {
+    self.set_atom(txr, id, slv.map(|(src, label, value)| (src, label, value.to_be_bytes().into())))
+  }
+
+  /// Sets atom to a string, encoded in UTF-8.
+  pub fn set_atom_str(&mut self, txr: &Transactor, id: u128, slv: Option<(u128, u64, &str)>) -> Result<(), StoreError> {
+    self.set_atom(txr, id, slv.map(|(src, label, value)| (src, label, value.as_bytes().into())))
+  }
+
+  /// Sets atom to a bool, encoded as a single `0` or `1` byte.
+  pub fn set_atom_bool(
+    &mut self,
+    txr: &Transactor,
+    id: u128,
+    slv: Option<(u128, u64, bool)>,
+  ) -> Result<(), StoreError> {
+    self.set_atom(txr, id, slv.map(|(src, label, value)| (src, label, [u8::from(value)].into())))
+  }
+
+  /// Changes the source of an existing edge, keeping its label and destination.
+  /// Does nothing if the edge does not exist.
+  pub fn set_edge_src(&mut self, txr: &Transactor, id: u128, src: u128) {
+    let Some((_, label, dst)) = self.edge(txr, id) else { return };
+    self.set_edge(txr, id, Some((src, label, dst)));
+  }
+
+  /// Changes the destination of an existing edge, keeping its source and label.
+  /// Does nothing if the edge does not exist.
+  pub fn set_edge_dst(&mut self, txr: &Transactor, id: u128, dst: u128) {
+    let Some((src, label, _)) = self.edge(txr, id) else { return };
+    self.set_edge(txr, id, Some((src, label, dst)));
+  }
+
+  /// Makes all modifications in order, then issues a barrier, returning all
+  /// events together.
+  pub fn apply_batch(
+    &mut self,
+    txr: &mut Transactor,
+    ops: impl IntoIterator<Item = Op>,
+  ) -> Result<Vec<CEventData>, StoreError> {
+    for op in ops {
+      match op {
+        Op::Node(id, label) => self.set_node(txr, id, label),
+        Op::Atom(id, slv) => self.set_atom(txr, id, slv)?,
+        Op::Edge(id, sld) => self.set_edge(txr, id, sld),
+      }
+    }
+    self.barrier(txr)
+  }
+
+  /// Sets node only if there is no record of it, in either saved data or pending
+  /// modifications. A removed node still counts as present, so that deletions
+  /// (including synced ones) are not undone. Returns whether it was created.
+  ///
+  /// As the transactor holds the write lock until committed, no other writer
+  /// can create the same node in between.
+  pub fn create_node_if_absent(&mut self, txr: &Transactor, id: u128, label: u64) -> bool {
+    if self.nodes.get(txr, id).is_some() {
+      return false;
+    }
+    self.set_node(txr, id, Some(label));
+    true
+  }
+
+  /// See [`Self::create_node_if_absent`].
+  pub fn create_atom_if_absent(
+    &mut self,
+    txr: &Transactor,
+    id: u128,
+    slv: (u128, u64, Box<[u8]>),
+  ) -> Result<bool, StoreError> {
+    if self.atoms.get(txr, id)?.is_some() {
+      return Ok(false);
+    }
+    self.set_atom(txr, id, Some(slv))?;
+    Ok(true)
+  }
+
+  /// See [`Self::create_node_if_absent`].
+  pub fn create_edge_if_absent(&mut self, txr: &Transactor, id: u128, sld: (u128, u64, u128)) -> bool {
+    if self.edges.get(txr, id).is_some() {
+      return false;
+    }
+    self.set_edge(txr, id, Some(sld));
+    true
+  }
+
+  /// Moves node `old` to id `new`, pointing all its atoms and edges (which keep
+  /// their own ids) to `new`. Does nothing and returns `false` if `old` does not
+  /// exist, or if there is any record of `new` (see [`Self::create_node_if_absent`]).
+  ///
+  /// Peers see this as `old` being removed and `new` being created. Concurrent
+  /// writes made elsewhere that still attach atoms or edges to `old` will win
+  /// if newer, and then get removed by the barrier since `old` is gone.
+  pub fn reassign_node(&mut self, txr: &Transactor, old: u128, new: u128) -> Result<bool, StoreError> {
+    let Some(label) = self.node(txr, old) else { return Ok(false) };
+    if self.nodes.get(txr, new).is_some() {
+      return Ok(false);
+    }
+    let rename = |id: u128| if id == old { new } else { id };
+    self.set_node(txr, new, Some(label));
+    for (atom, (label, value)) in self.atom_id_label_value_by_src(txr, old)? {
+      self.set_atom(txr, atom, Some((new, label, value)))?;
+    }
+    let mut edges = self.edge_id_label_dst_by_src(txr, old).into_keys().collect::<BTreeSet<_>>();
+    edges.extend(self.edge_id_src_label_by_dst(txr, old).into_keys());
+    for edge in edges {
+      if let Some((src, label, dst)) = self.edge(txr, edge) {
+        self.set_edge(txr, edge, Some((rename(src), label, rename(dst))));
+      }
+    }
+    self.set_node(txr, old, None);
+    Ok(true)
   }
 
   /// Issues write-read barrier: goes through all recent modifications,
@@ -133,11 +674,46 @@ impl Workspace {
   /// 1. `atom_implies_node`: all atoms must start from a node.
   /// 2. `edge_implies_node`: all edges must start from and ends at nodes.
   /// 3. `sticky_or_none`: for each node, if it has "sticky" atoms or edges
-  ///     attached to it at the previous barrier, those must be preserved,
-  ///     otherwise the node must be removed.
+  ///    attached to it at the previous barrier, those must be preserved,
+  ///    otherwise the node must be removed.
   /// 4. `acyclic_or_none`: edges marked as "acyclic" cannot form cycles,
-  ///     otherwise some edges must be removed to break the cycle.
-  pub fn barrier(&mut self, txr: &mut Transactor) -> Vec<CEventData> {
+  ///    otherwise some edges must be removed to break the cycle.
+  ///
+  /// Writes held back by [`Self::set_debounce`] are left pending.
+  pub fn barrier(&mut self, txr: &mut Transactor) -> Result<Vec<CEventData>, StoreError> {
+    self.barrier_with(txr, None, false)
+  }
+
+  /// Same as [`Self::barrier`], but also saves writes held back by
+  /// [`Self::set_debounce`].
+  pub fn flush(&mut self, txr: &mut Transactor) -> Result<Vec<CEventData>, StoreError> {
+    self.barrier_with(txr, None, true)
+  }
+
+  fn barrier_with(
+    &mut self,
+    txr: &mut Transactor,
+    replay: Option<Replay>,
+    flush: bool,
+  ) -> Result<Vec<CEventData>, StoreError> {
+    // Recent writes to debounced atoms are taken out after maintaining
+    // constraints and until after saving, as if they were made afterwards.
+    let mut held = Vec::new();
+    let now = Instant::now();
+    for (id, until) in std::mem::take(&mut self.held) {
+      match self.atoms.clock_of(txr, id)? {
+        Some(clock) if !flush && until > now && clock.0 == self.this() => held.push((id, until, clock)),
+        _ => {}
+      }
+    }
+
+    // Writes made below to maintain constraints belong to the same undo step as
+    // the local writes causing them. Without any, they were caused by joined
+    // actions, and are not for the user to undo.
+    let mut local = self.local_step(txr)?;
+    local.atoms.retain(|(id, _, _)| !held.iter().any(|(held, _, _)| held == id));
+    let local = !local.is_empty();
+
     // Assuming all conditions were true before any of the modifications,
     // we only need to focus on changes which cause violations.
 
@@ -196,7 +772,7 @@ impl Workspace {
     }
 
     while let Some(id) = atoms.pop_first() {
-      self.set_atom(txr, id, None);
+      self.set_atom(txr, id, None)?;
     }
     while let Some(id) = edges.pop_first() {
       self.set_edge(txr, id, None);
@@ -205,8 +781,8 @@ impl Workspace {
       if self.nodes.exists(txr, id) {
         self.set_node(txr, id, None);
       }
-      for (atom, _) in self.atom_id_label_value_by_src(txr, id) {
-        self.set_atom(txr, atom, None);
+      for (atom, _) in self.atom_id_label_value_by_src(txr, id)? {
+        self.set_atom(txr, atom, None)?;
       }
       for (edge, _) in self.edge_id_label_dst_by_src(txr, id) {
         self.set_edge(txr, edge, None);
@@ -219,6 +795,13 @@ impl Workspace {
       }
     }
 
+    let mut taken = Vec::new();
+    for (id, until, clock) in held {
+      if self.atoms.clock_of(txr, id)? == Some(clock) {
+        taken.extend(self.atoms.take(id).map(|item| (id, until, item)));
+      }
+    }
+
     // Collect all modifications.
     let mut res = Vec::new();
     for (id, prev, curr) in self.nodes.mods() {
@@ -231,12 +814,162 @@ impl Workspace {
       res.push(CEventData::Edge { id: id.into(), prev: prev.map(Into::into).into(), curr: curr.map(Into::into).into() })
     }
 
-    // Apply and save all modifications.
+    // Apply and save all modifications, then record local ones for undo.
+    let step = self.local_step(txr)?;
     self.nodes.save(txr);
-    self.atoms.save(txr);
+    self.atoms.save(txr)?;
     self.edges.save(txr);
+    if local && !step.is_empty() {
+      if replay.is_none() {
+        self.redo.clear();
+      }
+      if replay == Some(Replay::Undo) {
+        self.redo.push(step);
+      } else {
+        if self.undo.len() == MAX_UNDO {
+          self.undo.pop_front();
+        }
+        self.undo.push_back(step);
+      }
+    }
 
-    res
+    for (id, until, item) in taken {
+      if self.atoms.restore(txr, id, item)? {
+        self.held.insert(id, until);
+      }
+    }
+
+    self.count(|metrics| metrics.events_emitted += res.len() as u64);
+    Ok(res)
+  }
+
+  /// Reverts local changes saved by the last [`Self::barrier`] call, returning
+  /// events in the same way. Pending modifications (including held ones) are
+  /// saved first, as a step of their own.
+  ///
+  /// The reversal is a new write, synced like any other. Items changed since,
+  /// either locally or by peers, are left as they are. Only the last
+  /// [`MAX_UNDO`] steps are kept, in memory.
+  pub fn undo(&mut self, txr: &mut Transactor) -> Result<Vec<CEventData>, StoreError> {
+    let mut res = self.flush(txr)?;
+    if let Some(step) = self.undo.pop_back() {
+      self.revert(txr, step)?;
+      res.extend(self.barrier_with(txr, Some(Replay::Undo), true)?);
+    }
+    Ok(res)
+  }
+
+  /// Reverts the last [`Self::undo`], in the same way. Any other local changes
+  /// made since then discard all redo steps.
+  pub fn redo(&mut self, txr: &mut Transactor) -> Result<Vec<CEventData>, StoreError> {
+    let mut res = self.flush(txr)?;
+    if let Some(step) = self.redo.pop() {
+      self.revert(txr, step)?;
+      res.extend(self.barrier_with(txr, Some(Replay::Redo), true)?);
+    }
+    Ok(res)
+  }
+
+  fn local_step(&self, txr: &Transactor) -> Result<Step, StoreError> {
+    let this = self.metadata.this();
+    let mut res = Step::default();
+    for (id, prev, curr) in self.nodes.mods() {
+      if self.nodes.get(txr, id).is_some_and(|(bucket, _, _)| bucket == this) {
+        res.nodes.push((id, prev, curr));
+      }
+    }
+    for (id, prev, curr) in self.atoms.mods() {
+      if self.atoms.clock_of(txr, id)?.is_some_and(|(bucket, _)| bucket == this) {
+        res.atoms.push((id, prev, curr));
+      }
+    }
+    for (id, prev, curr) in self.edges.mods() {
+      if self.edges.get(txr, id).is_some_and(|(bucket, _, _)| bucket == this) {
+        res.edges.push((id, prev, curr));
+      }
+    }
+    Ok(res)
+  }
+
+  /// Restores items to `prev`, if they were last written locally and still
+  /// have the value `curr`. (Clocks are not compared, as undoing and redoing
+  /// make new writes too.)
+  fn revert(&mut self, txr: &Transactor, step: Step) -> Result<(), StoreError> {
+    let this = self.metadata.this();
+    for (id, prev, curr) in step.nodes {
+      if self.nodes.get(txr, id).is_some_and(|(bucket, _, l)| bucket == this && l == curr) {
+        self.set_node(txr, id, prev);
+      }
+    }
+    for (id, prev, curr) in step.atoms {
+      if self.atoms.get(txr, id)?.is_some_and(|(bucket, _, slv)| bucket == this && slv == curr) {
+        self.set_atom(txr, id, prev)?;
+      }
+    }
+    for (id, prev, curr) in step.edges {
+      if self.edges.get(txr, id).is_some_and(|(bucket, _, sld)| bucket == this && sld == curr) {
+        self.set_edge(txr, id, prev);
+      }
+    }
+    Ok(())
+  }
+
+  /// Repairs saved clock values which fall behind the data they describe.
+  /// Saves happen within a single transaction, so this should be a no-op
+  /// unless an earlier save was interrupted between separate transactions;
+  /// run it at startup, before making any modifications.
+  pub fn recover(&mut self, txr: &mut Transactor) -> Result<bool, StoreError> {
+    let nodes = self.nodes.recover(txr);
+    let atoms = self.atoms.recover(txr)?;
+    let edges = self.edges.recover(txr);
+    Ok(nodes || atoms || edges)
+  }
+
+  /// Checks invariants which barriers and saves maintain, listing every
+  /// violation found. These only arise from outside interference, such as
+  /// corruption or writes made without this library. Pending modifications
+  /// are included, so call this right after a barrier.
+  ///
+  /// This reads the whole workspace into memory.
+  pub fn check_integrity(&self, txr: &Transactor) -> Result<Vec<Anomaly>, StoreError> {
+    let mut res = Vec::new();
+    let nodes = self.nodes.actions(txr, BTreeMap::new());
+    let exists = |id: &u128| nodes.get(id).is_some_and(|(_, _, label)| label.is_some());
+    for (atom, (_, _, slv)) in self.atoms.actions(txr, BTreeMap::new())? {
+      if let Some((src, _, _)) = slv.filter(|(src, _, _)| !exists(src)) {
+        res.push(Anomaly::AtomWithoutNode { atom, src });
+      }
+    }
+    for (edge, (_, _, sld)) in self.edges.actions(txr, BTreeMap::new()) {
+      if let Some((src, _, dst)) = sld {
+        let missing = if src == dst { vec![src] } else { vec![src, dst] };
+        res
+          .extend(missing.into_iter().filter(|node| !exists(node)).map(|node| Anomaly::EdgeWithoutNode { edge, node }));
+      }
+    }
+    let behind = [
+      (NODES_NAME, self.nodes.clocks_behind(txr)),
+      (ATOMS_NAME, self.atoms.clocks_behind(txr)?),
+      (EDGES_NAME, self.edges.clocks_behind(txr)),
+    ];
+    for (name, buckets) in behind {
+      for (bucket, (recorded, found)) in buckets {
+        res.push(Anomaly::ClockBehind { name, bucket, recorded, found });
+      }
+    }
+    Ok(res)
+  }
+
+  /// Discards all modifications since the previous barrier, including those
+  /// introduced by [`Workspace::sync_join`]. Until the next barrier, reads see
+  /// committed data overlaid with these pending modifications; afterwards they
+  /// see committed data only. Discarded remote actions will be sent again by
+  /// the peer, since the corresponding clock values are rolled back as well.
+  pub fn discard(&mut self) {
+    self.held.clear();
+    self.nodes.discard();
+    self.atoms.discard();
+    self.edges.discard();
   }
 
   /// Used in checking acyclicity constraints.
@@ -255,54 +988,249 @@ impl Workspace {
 
   /// To keep backward compatibility, do not change existing strings and type
   /// annotations below. Additional entries may be added.
-  pub fn sync_version(&self, _: &Transactor) -> Box<[u8]> {
-    let nodes_version: BTreeMap<u64, u64> = self.nodes.buckets();
-    let atoms_version: BTreeMap<u64, u64> = self.atoms.buckets();
-    let edges_version: BTreeMap<u64, u64> = self.edges.buckets();
+  pub fn sync_version(&self, txr: &Transactor) -> Box<[u8]> {
+    self.sync_version_for(txr, &[NODES_NAME, ATOMS_NAME, EDGES_NAME])
+  }
 
-    let all: BTreeMap<&str, Vec<u8>> = BTreeMap::from([
-      (NODES_NAME, serialize(&nodes_version).unwrap()),
-      (ATOMS_NAME, serialize(&atoms_version).unwrap()),
-      (EDGES_NAME, serialize(&edges_version).unwrap()),
-    ]);
+  /// Like [`Self::sync_version`], but only covering the structures in `names`,
+  /// so that structures can be synced on independent schedules using
+  /// [`Self::sync_actions_for`].
+  ///
+  /// Nodes are always covered: the barrier removes atoms and edges whose
+  /// nodes are missing, so syncing them ahead of their nodes would lose data.
+  pub fn sync_version_for(&self, _: &Transactor, names: &[&str]) -> Box<[u8]> {
+    let mut all: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    all.insert(NODES_NAME, serialize(&self.nodes.buckets()).unwrap());
+    if names.contains(&ATOMS_NAME) {
+      all.insert(ATOMS_NAME, serialize(&self.atoms.buckets()).unwrap());
+    }
+    if names.contains(&EDGES_NAME) {
+      all.insert(EDGES_NAME, serialize(&self.edges.buckets()).unwrap());
+    }
+
+    frame(&serialize(&all).unwrap()).into()
+  }
 
-    serialize(&all).unwrap().into()
+  /// Returns an order-independent digest of the current contents of the
+  /// structure `name` (one of the names used in sync), or `None` if there is
+  /// no such structure. Replicas that have converged produce the same digest,
+  /// regardless of the order actions were joined or which tombstones have been
+  /// removed, so comparing digests after a sync detects divergence without
+  /// resending data.
+  ///
+  /// This is the XOR of a hash of each non-removed item, which is kept up to
+  /// date on every write and saved along with the data, so it takes constant
+  /// time. It is not meant to resist deliberate collisions.
+  pub fn structure_digest(&self, _: &Transactor, name: &str) -> Result<Option<u128>, StoreError> {
+    Ok(match name {
+      NODES_NAME => Some(self.nodes.digest()),
+      ATOMS_NAME => Some(self.atoms.digest()),
+      EDGES_NAME => Some(self.edges.digest()),
+      _ => None,
+    })
   }
 
   /// To keep backward compatibility, do not change existing strings and type
   /// annotations below. Additional entries may be added.
-  pub fn sync_actions(&self, txr: &Transactor, version: &[u8]) -> Box<[u8]> {
-    let all: BTreeMap<String, &[u8]> = deserialize(version).unwrap();
+  pub fn sync_actions(&self, txr: &Transactor, version: &[u8]) -> Result<Box<[u8]>, StoreError> {
+    let res = self.actions(txr, version, true)?;
+    self.count(|metrics| metrics.bytes_synced_out += res.len() as u64);
+    Ok(res)
+  }
 
-    let nodes_version: BTreeMap<u64, u64> = all.get(NODES_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
-    let atoms_version: BTreeMap<u64, u64> = all.get(ATOMS_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
-    let edges_version: BTreeMap<u64, u64> = all.get(EDGES_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
+  /// Returns the current state of every structure, including pending
+  /// modifications, in the same format as [`Self::sync_actions`]. Only the
+  /// latest write to each item is kept, but removals are included: without
+  /// them, older writes from other replicas could bring removed items back.
+  pub fn sync_snapshot(&self, txr: &Transactor) -> Result<Box<[u8]>, StoreError> {
+    let empty: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    self.actions(txr, &frame(&serialize(&empty).unwrap()), true)
+  }
 
-    let nodes_actions: BTreeMap<u128, (u64, u64, Option<u64>)> = self.nodes.actions(txr, nodes_version);
-    let atoms_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, Box<[u8]>)>)> =
-      self.atoms.actions(txr, atoms_version);
-    let edges_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, u128)>)> = self.edges.actions(txr, edges_version);
+  /// Like [`Self::sync_actions`], but only returns actions for structures
+  /// covered by `version` (see [`Self::sync_version_for`]), instead of
+  /// treating missing ones as empty.
+  pub fn sync_actions_for(&self, txr: &Transactor, version: &[u8]) -> Result<Box<[u8]>, StoreError> {
+    let res = self.actions(txr, version, false)?;
+    self.count(|metrics| metrics.bytes_synced_out += res.len() as u64);
+    Ok(res)
+  }
 
-    let all: BTreeMap<&str, Vec<u8>> = BTreeMap::from([
-      (NODES_NAME, serialize(&nodes_actions).unwrap()),
-      (ATOMS_NAME, serialize(&atoms_actions).unwrap()),
-      (EDGES_NAME, serialize(&edges_actions).unwrap()),
-    ]);
+  /// Like [`Self::sync_actions`], but returns at most about `max_bytes` of
+  /// actions (always at least one, if there are any left), together with a
+  /// cursor if there are more. The cursor is a version to be passed to the next
+  /// call in place of the original one.
+  ///
+  /// Nodes are sent before atoms and edges, and actions from each client in the
+  /// order they were made, so each chunk may be joined and committed on its
+  /// own. However, constraints are only guaranteed to be preserved in the same
+  /// way as [`Self::sync_actions`] after all chunks are joined.
+  pub fn sync_actions_chunk(
+    &self,
+    txr: &Transactor,
+    version: &[u8],
+    max_bytes: usize,
+  ) -> Result<(Box<[u8]>, Option<Box<[u8]>>), StoreError> {
+    let all: BTreeMap<String, &[u8]> = deserialize(unframe(version)?)?;
+    let mut nodes_version: BTreeMap<u64, u64> =
+      all.get(NODES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+    let mut atoms_version: BTreeMap<u64, u64> =
+      all.get(ATOMS_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+    let mut edges_version: BTreeMap<u64, u64> =
+      all.get(EDGES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+
+    let mut used = 0;
+    let mut res: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    let (nodes_actions, mut done) =
+      take_chunk(self.nodes.actions(txr, nodes_version.clone()), &mut nodes_version, &mut used, max_bytes);
+    res.insert(NODES_NAME, serialize(&nodes_actions).unwrap());
+    if done {
+      let atoms_actions;
+      (atoms_actions, done) =
+        take_chunk(self.atoms.actions(txr, atoms_version.clone())?, &mut atoms_version, &mut used, max_bytes);
+      res.insert(ATOMS_NAME, serialize(&atoms_actions).unwrap());
+    }
+    if done {
+      let edges_actions;
+      (edges_actions, done) =
+        take_chunk(self.edges.actions(txr, edges_version.clone()), &mut edges_version, &mut used, max_bytes);
+      res.insert(EDGES_NAME, serialize(&edges_actions).unwrap());
+    }
+
+    let cursor = (!done).then(|| {
+      let mut all: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+      all.insert(NODES_NAME, serialize(&nodes_version).unwrap());
+      all.insert(ATOMS_NAME, serialize(&atoms_version).unwrap());
+      all.insert(EDGES_NAME, serialize(&edges_version).unwrap());
+      frame(&serialize(&all).unwrap()).into()
+    });
+    let res: Box<[u8]> = frame(&serialize(&res).unwrap()).into();
+    self.count(|metrics| metrics.bytes_synced_out += res.len() as u64);
+    Ok((res, cursor))
+  }
+
+  fn actions(&self, txr: &Transactor, version: &[u8], all_names: bool) -> Result<Box<[u8]>, StoreError> {
+    let all: BTreeMap<String, &[u8]> = deserialize(unframe(version)?)?;
+    let mut res: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
 
-    serialize(&all).unwrap().into()
+    if all_names || all.contains_key(NODES_NAME) {
+      let nodes_version: BTreeMap<u64, u64> =
+        all.get(NODES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+      let nodes_actions: BTreeMap<u128, (u64, u64, Option<u64>)> = self.nodes.actions(txr, nodes_version);
+      res.insert(NODES_NAME, serialize(&nodes_actions).unwrap());
+    }
+    if all_names || all.contains_key(ATOMS_NAME) {
+      let atoms_version: BTreeMap<u64, u64> =
+        all.get(ATOMS_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+      let atoms_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, Box<[u8]>)>)> =
+        self.atoms.actions(txr, atoms_version)?;
+      res.insert(ATOMS_NAME, serialize(&atoms_actions).unwrap());
+    }
+    if all_names || all.contains_key(EDGES_NAME) {
+      let edges_version: BTreeMap<u64, u64> =
+        all.get(EDGES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+      let edges_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, u128)>)> = self.edges.actions(txr, edges_version);
+      res.insert(EDGES_NAME, serialize(&edges_actions).unwrap());
+    }
+
+    Ok(frame(&serialize(&res).unwrap()).into())
   }
 
   /// To keep backward compatibility, do not change existing strings and type
   /// annotations below. Additional entries may be added.
-  pub fn sync_join(&mut self, txr: &Transactor, actions: &[u8]) {
-    let all: BTreeMap<String, &[u8]> = deserialize(actions).unwrap();
+  ///
+  /// Actions under [`Self::this`] newer than any written here are counted in
+  /// [`SyncStats::unknown_own`], or rejected (see
+  /// [`Self::set_reject_unknown_own`]).
+  pub fn sync_join(&mut self, txr: &Transactor, actions: &[u8]) -> Result<SyncStats, StoreError> {
+    let (nodes_actions, atoms_actions, edges_actions, unknown_own) = self.sync_decode(actions)?;
+    let mut stats = SyncStats { unknown_own, ..Default::default() };
+    stats.add(NODES_NAME, action_clocks(&nodes_actions), self.nodes.join(txr, nodes_actions));
+    stats.add(ATOMS_NAME, action_clocks(&atoms_actions), self.atoms.join(txr, atoms_actions)?);
+    stats.add(EDGES_NAME, action_clocks(&edges_actions), self.edges.join(txr, edges_actions));
+    self.count_join(actions.len(), &stats);
+    Ok(stats)
+  }
+
+  /// Same as [`Self::sync_join`], additionally calling `on_conflict` for each
+  /// incoming action dropped because a newer write to the same item is already
+  /// present, e.g. to tell the user that an offline edit was overwritten.
+  ///
+  /// This is called while joining, before the next barrier, so the winning
+  /// item may still be removed by the barrier if it violates constraints.
+  pub fn sync_join_with(
+    &mut self,
+    txr: &Transactor,
+    actions: &[u8],
+    mut on_conflict: impl FnMut(Conflict),
+  ) -> Result<SyncStats, StoreError> {
+    let (nodes_actions, atoms_actions, edges_actions, unknown_own) = self.sync_decode(actions)?;
+    let mut stats = SyncStats { unknown_own, ..Default::default() };
+
+    let clocks = action_clocks(&nodes_actions);
+    let outcomes = self.nodes.join(txr, nodes_actions.iter().cloned());
+    for ((id, dropped), (_, outcome)) in nodes_actions.into_iter().zip(&outcomes) {
+      if *outcome == Outcome::RejectedOlder {
+        on_conflict(Conflict::Node { id, dropped, winning: self.nodes.get(txr, id) });
+      }
+    }
+    stats.add(NODES_NAME, clocks, outcomes);
+
+    let clocks = action_clocks(&atoms_actions);
+    let outcomes = self.atoms.join(txr, atoms_actions.iter().cloned())?;
+    for ((id, dropped), (_, outcome)) in atoms_actions.into_iter().zip(&outcomes) {
+      if *outcome == Outcome::RejectedOlder {
+        on_conflict(Conflict::Atom { id, dropped, winning: self.atoms.get(txr, id)? });
+      }
+    }
+    stats.add(ATOMS_NAME, clocks, outcomes);
+
+    let clocks = action_clocks(&edges_actions);
+    let outcomes = self.edges.join(txr, edges_actions.iter().cloned());
+    for ((id, dropped), (_, outcome)) in edges_actions.into_iter().zip(&outcomes) {
+      if *outcome == Outcome::RejectedOlder {
+        on_conflict(Conflict::Edge { id, dropped, winning: self.edges.get(txr, id) });
+      }
+    }
+    stats.add(EDGES_NAME, clocks, outcomes);
+
+    self.count_join(actions.len(), &stats);
+    Ok(stats)
+  }
+
+  /// Decodes actions for [`Self::sync_join`], sorted in the order to be joined,
+  /// together with the count for [`SyncStats::unknown_own`].
+  fn sync_decode(
+    &self,
+    actions: &[u8],
+  ) -> Result<
+    (
+      Vec<(u128, (u64, u64, Option<u64>))>,
+      Vec<(u128, (u64, u64, Option<(u128, u64, Box<[u8]>)>))>,
+      Vec<(u128, (u64, u64, Option<(u128, u64, u128)>))>,
+      u64,
+    ),
+    StoreError,
+  > {
+    let all: BTreeMap<String, &[u8]> = deserialize(unframe(actions)?)?;
 
     let nodes_actions: BTreeMap<u128, (u64, u64, Option<u64>)> =
-      all.get(NODES_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
+      all.get(NODES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
     let atoms_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, Box<[u8]>)>)> =
-      all.get(ATOMS_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
+      all.get(ATOMS_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
     let edges_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, u128)>)> =
-      all.get(EDGES_NAME).map_or_else(BTreeMap::new, |m| deserialize(m).unwrap());
+      all.get(EDGES_NAME).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+
+    let this = self.metadata.this();
+    let (nodes_next, atoms_next, edges_next) =
+      (self.nodes.next_for_bucket(this), self.atoms.next_for_bucket(this), self.edges.next_for_bucket(this));
+    let unknown_own =
+      nodes_actions.values().filter(|(bucket, clock, _)| *bucket == this && *clock >= nodes_next).count()
+        + atoms_actions.values().filter(|(bucket, clock, _)| *bucket == this && *clock >= atoms_next).count()
+        + edges_actions.values().filter(|(bucket, clock, _)| *bucket == this && *clock >= edges_next).count();
+    if self.reject_unknown_own && unknown_own > 0 {
+      return Err(StoreError::DuplicateReplica);
+    }
 
     let mut nodes_actions = nodes_actions.into_iter().collect::<Vec<_>>();
     nodes_actions.sort_by_key(|(_, (bucket, clock, _))| (*bucket, *clock));
@@ -311,16 +1239,154 @@ impl Workspace {
     let mut edges_actions = edges_actions.into_iter().collect::<Vec<_>>();
     edges_actions.sort_by_key(|(_, (bucket, clock, _))| (*bucket, *clock));
 
-    for (id, (bucket, clock, l)) in nodes_actions {
-      self.nodes.set(txr, id, bucket, clock, l);
+    Ok((nodes_actions, atoms_actions, edges_actions, unknown_own as u64))
+  }
+
+  /// Physically deletes removed nodes, atoms and edges which all peers are
+  /// known to have seen, given the latest [`Self::sync_version`] from each of
+  /// them. Returns the number deleted. With no peers, nothing is deleted.
+  ///
+  /// Any peer left out may never learn of the removals, so `versions` must
+  /// cover every peer which may still sync with this one.
+  pub fn remove_tombstones(&mut self, txr: &mut Transactor, versions: &[&[u8]]) -> Result<usize, StoreError> {
+    let versions = versions
+      .iter()
+      .map(|version| Ok(deserialize::<BTreeMap<String, &[u8]>>(unframe(version)?)?))
+      .collect::<Result<Vec<_>, StoreError>>()?;
+    let acknowledged = |name: &str| -> Result<BTreeMap<u64, u64>, StoreError> {
+      let mut res: Option<BTreeMap<u64, u64>> = None;
+      for all in &versions {
+        let version: BTreeMap<u64, u64> = all.get(name).map_or_else(|| Ok(BTreeMap::new()), |m| deserialize(m))?;
+        res = Some(match res {
+          None => version,
+          Some(prev) => {
+            prev.into_iter().filter_map(|(bucket, clock)| Some((bucket, clock.min(*version.get(&bucket)?)))).collect()
+          }
+        });
+      }
+      Ok(res.unwrap_or_default())
+    };
+    let (nodes, atoms, edges) = (acknowledged(NODES_NAME)?, acknowledged(ATOMS_NAME)?, acknowledged(EDGES_NAME)?);
+    let mut res = self.nodes.remove_tombstones(txr, &nodes);
+    res += self.atoms.remove_tombstones(txr, &atoms)?;
+    res += self.edges.remove_tombstones(txr, &edges);
+    Ok(res)
+  }
+
+  /// Collects the current state of `id` and everything around it into an
+  /// actions blob accepted by [`Self::sync_join`], for reproducing issues on a
+  /// fresh workspace. `id` may be a node, atom or edge. Nodes reached through
+  /// edges (in either direction) are followed up to `depth` steps.
+  ///
+  /// This is best-effort: only current states (not past actions) are included,
+  /// and anything further than `depth` edges away is left out, together with
+  /// the edges leading to it.
+  pub fn sync_actions_touching(&self, txr: &Transactor, id: u128, depth: u64) -> Result<Box<[u8]>, StoreError> {
+    let mut nodes_actions: BTreeMap<u128, (u64, u64, Option<u64>)> = BTreeMap::new();
+    let mut atoms_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, Box<[u8]>)>)> = BTreeMap::new();
+    let mut edges_actions: BTreeMap<u128, (u64, u64, Option<(u128, u64, u128)>)> = BTreeMap::new();
+
+    let mut frontier = vec![id];
+    if let Some(item) = self.atoms.get(txr, id)? {
+      if let Some((src, _, _)) = item.2 {
+        frontier.push(src);
+      }
+      atoms_actions.insert(id, item);
+    }
+    if let Some(item) = self.edges.get(txr, id) {
+      if let Some((src, _, dst)) = item.2 {
+        frontier.extend([src, dst]);
+      }
+      edges_actions.insert(id, item);
     }
-    for (id, (bucket, clock, slv)) in atoms_actions {
-      self.atoms.set(txr, id, bucket, clock, slv);
+
+    let mut visited = BTreeSet::new();
+    for step in 0..=depth {
+      let mut next = Vec::new();
+      for node in frontier {
+        if !visited.insert(node) {
+          continue;
+        }
+        if let Some(item) = self.nodes.get(txr, node) {
+          nodes_actions.insert(node, item);
+        }
+        for atom in self.atoms.id_label_value_by_src(txr, node)?.into_keys() {
+          atoms_actions.extend(self.atoms.get(txr, atom)?.map(|item| (atom, item)));
+        }
+        let outgoing = self.edges.id_label_dst_by_src(txr, node).into_iter().map(|(edge, (_, dst))| (edge, dst));
+        let incoming = self.edges.id_src_label_by_dst(txr, node).into_iter().map(|(edge, (src, _))| (edge, src));
+        for (edge, other) in outgoing.chain(incoming) {
+          edges_actions.extend(self.edges.get(txr, edge).map(|item| (edge, item)));
+          if step < depth {
+            next.push(other);
+          }
+        }
+      }
+      frontier = next;
     }
-    for (id, (bucket, clock, sld)) in edges_actions {
-      self.edges.set(txr, id, bucket, clock, sld);
+    // Edges leading out of the collected part would be removed by the barrier
+    // on the receiving side, with a clock newer than the actual edge.
+    edges_actions
+      .retain(|_, (_, _, sld)| sld.as_ref().is_none_or(|(src, _, dst)| visited.contains(src) && visited.contains(dst)));
+
+    let all: BTreeMap<&str, Vec<u8>> = BTreeMap::from([
+      (NODES_NAME, serialize(&nodes_actions).unwrap()),
+      (ATOMS_NAME, serialize(&atoms_actions).unwrap()),
+      (EDGES_NAME, serialize(&edges_actions).unwrap()),
+    ]);
+
+    let res: Box<[u8]> = frame(&serialize(&all).unwrap()).into();
+    self.count(|metrics| metrics.bytes_synced_out += res.len() as u64);
+    Ok(res)
+  }
+}
+
+/// Drops all tables of the workspace with given prefix, including those of
+/// structures created separately under it, returning how many were dropped.
+/// This is part of the current transaction, and any [`Workspace`] still using
+/// the prefix must not be used afterwards.
+///
+/// Tables are recognised by name, which assumes that structure names do not
+/// contain `.`; this keeps prefixes such as `a` and `a.b` apart.
+pub fn drop_workspace(txr: &mut Transactor, prefix: &str) -> Result<usize, StoreError> {
+  let start = format!("{prefix}.");
+  let owned = |table: &str| {
+    let Some(rest) = table.strip_prefix(&start) else { return false };
+    let name =
+      rest.strip_suffix(".data").or_else(|| rest.strip_suffix(".buckets")).or_else(|| rest.strip_suffix(".digest"));
+    rest == "version" || rest == "this" || name.is_some_and(|name| !name.is_empty() && !name.contains('.'))
+  };
+  let mut stmt = txr.prepare("SELECT name FROM sqlite_schema WHERE type = 'table'")?;
+  let tables = stmt.query_map((), |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+  drop(stmt);
+  let tables = tables.into_iter().filter(|table| owned(table)).collect::<Vec<_>>();
+  for table in &tables {
+    txr.execute_batch(&format!("DROP TABLE \"{table}\";"))?;
+  }
+  Ok(tables.len())
+}
+
+/// Takes actions in `(bucket, clock)` order while they fit, raising `version`
+/// to the last one taken from each bucket. Returns whether all were taken.
+fn take_chunk<T: serde::Serialize>(
+  actions: BTreeMap<u128, (u64, u64, T)>,
+  version: &mut BTreeMap<u64, u64>,
+  used: &mut usize,
+  max_bytes: usize,
+) -> (BTreeMap<u128, (u64, u64, T)>, bool) {
+  let mut actions = actions.into_iter().collect::<Vec<_>>();
+  actions.sort_by_key(|(_, (bucket, clock, _))| (*bucket, *clock));
+  let mut res = BTreeMap::new();
+  for (id, item) in actions {
+    let size = serialize(&(id, &item)).unwrap().len();
+    if *used > 0 && *used + size > max_bytes {
+      return (res, false);
     }
+    *used += size;
+    version.insert(item.0, item.1);
+    res.insert(id, item);
   }
+  (res, true)
 }
 
 #[cfg(test)]
@@ -339,7 +1405,7 @@ mod tests {
     constraints.add_sticky_node(100);
     constraints.add_sticky_atom(200);
     constraints.add_sticky_edge(300);
-    let mut ws = Workspace::new("", constraints, &mut txr);
+    let mut ws = Workspace::new("", constraints, &mut txr).unwrap();
 
     let node0 = rng.gen();
     let node1 = rng.gen();
@@ -355,7 +1421,7 @@ mod tests {
     ws.set_edge(&txr, rng.gen(), Some((node1, 3, node0)));
     ws.set_edge(&txr, rng.gen(), Some((node1, 2, 2333))); // Invalid
     ws.set_edge(&txr, rng.gen(), Some((2333, 2, node1))); // Invalid
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert_eq!(ws.node(&txr, node0), Some(0));
     assert_eq!(ws.node(&txr, node1), Some(100));
     assert_eq!(ws.edge_id_label_dst_by_src(&txr, node0).len(), 2);
@@ -367,7 +1433,7 @@ mod tests {
     ws.set_node(&txr, node1, Some(2333)); // Invalid
     ws.set_edge(&txr, rng.gen(), Some((node0, 3, node1))); // Invalid
     ws.set_edge(&txr, rng.gen(), Some((node1, 3, node0))); // Invalid
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert_eq!(ws.node(&txr, node0), Some(2333));
     assert_eq!(ws.node(&txr, node1), None);
     assert_eq!(ws.edge_id_label_dst_by_src(&txr, node0).len(), 1);
@@ -378,24 +1444,24 @@ mod tests {
     let atom0 = rng.gen();
     let atom1 = rng.gen();
     let atom2 = rng.gen();
-    ws.set_atom(&txr, atom0, Some((node0, 1, vec![1, 2, 3, 4].into())));
-    ws.set_atom(&txr, atom1, Some((node0, 200, vec![].into()))); // Overwritten
-    ws.set_atom(&txr, atom1, Some((node0, 0, vec![].into()))); // Overwritten
-    ws.set_atom(&txr, atom1, Some((node0, 200, vec![5, 6, 7].into())));
-    ws.set_atom(&txr, atom2, Some((node2, 2, vec![].into())));
-    ws.barrier(&mut txr);
-    assert!(ws.atom(&txr, atom0).is_some());
-    assert!(ws.atom(&txr, atom1).is_some());
-    assert!(ws.atom(&txr, atom2).is_some());
-
-    ws.set_atom(&txr, atom0, Some((node2, 1, vec![].into())));
-    ws.set_atom(&txr, atom1, Some((node2, 200, vec![].into()))); // Invalid, delete `node0`
-    ws.set_atom(&txr, atom2, Some((node0, 2, vec![].into()))); // Invalid, `node0` deleted
-    ws.barrier(&mut txr);
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![1, 2, 3, 4].into()))).unwrap();
+    ws.set_atom(&txr, atom1, Some((node0, 200, vec![].into()))).unwrap(); // Overwritten
+    ws.set_atom(&txr, atom1, Some((node0, 0, vec![].into()))).unwrap(); // Overwritten
+    ws.set_atom(&txr, atom1, Some((node0, 200, vec![5, 6, 7].into()))).unwrap();
+    ws.set_atom(&txr, atom2, Some((node2, 2, vec![].into()))).unwrap();
+    ws.barrier(&mut txr).unwrap();
+    assert!(ws.atom(&txr, atom0).unwrap().is_some());
+    assert!(ws.atom(&txr, atom1).unwrap().is_some());
+    assert!(ws.atom(&txr, atom2).unwrap().is_some());
+
+    ws.set_atom(&txr, atom0, Some((node2, 1, vec![].into()))).unwrap();
+    ws.set_atom(&txr, atom1, Some((node2, 200, vec![].into()))).unwrap(); // Invalid, delete `node0`
+    ws.set_atom(&txr, atom2, Some((node0, 2, vec![].into()))).unwrap(); // Invalid, `node0` deleted
+    ws.barrier(&mut txr).unwrap();
     assert!(ws.node(&txr, node0).is_none());
-    assert!(ws.atom(&txr, atom0).is_some());
-    assert!(ws.atom(&txr, atom1).is_some());
-    assert!(ws.atom(&txr, atom2).is_none());
+    assert!(ws.atom(&txr, atom0).unwrap().is_some());
+    assert!(ws.atom(&txr, atom1).unwrap().is_some());
+    assert!(ws.atom(&txr, atom2).unwrap().is_none());
 
     let edge0 = rng.gen();
     let edge1 = rng.gen();
@@ -405,7 +1471,7 @@ mod tests {
     ws.set_edge(&txr, edge1, Some((node3, 2, node1))); // Invalid
     ws.set_edge(&txr, edge2, Some((node3, 300, node2)));
     ws.set_edge(&txr, edge3, Some((node3, 300, node3)));
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert!(ws.node(&txr, node2).is_some());
     assert!(ws.node(&txr, node3).is_some());
     assert!(ws.edge(&txr, edge0).is_none());
@@ -414,7 +1480,7 @@ mod tests {
     assert!(ws.edge(&txr, edge3).is_some());
 
     ws.set_edge(&txr, rng.gen(), Some((node2, 300, node0))); // Invalid, delete `node2` (?) and `node3`
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert!(ws.node(&txr, node2).is_none());
     assert!(ws.node(&txr, node3).is_none());
 
@@ -427,14 +1493,14 @@ mod tests {
       ws.set_edge(&txr, edges[i], Some((nodes[i], 300, nodes[i + rng.gen_range(1..=(N - i))])));
     }
     ws.set_node(&txr, nodes[N], Some(0));
-    ws.set_atom(&txr, atom, Some((nodes[N], 200, vec![].into())));
-    ws.barrier(&mut txr);
+    ws.set_atom(&txr, atom, Some((nodes[N], 200, vec![].into()))).unwrap();
+    ws.barrier(&mut txr).unwrap();
     for i in 0..N {
       assert!(ws.node(&txr, nodes[i]).is_some());
       assert!(ws.edge(&txr, edges[i]).is_some());
     }
-    ws.set_atom(&txr, atom, Some((nodes[N], 2333, vec![].into()))); // Invalid, delete `nodes` and `edges`
-    ws.barrier(&mut txr);
+    ws.set_atom(&txr, atom, Some((nodes[N], 2333, vec![].into()))).unwrap(); // Invalid, delete `nodes` and `edges`
+    ws.barrier(&mut txr).unwrap();
     for i in 0..N {
       assert!(ws.node(&txr, nodes[i]).is_none());
       assert!(ws.edge(&txr, edges[i]).is_none());
@@ -454,7 +1520,7 @@ mod tests {
     for round in 50..100 {
       let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
       let mut rng = rand::thread_rng();
-      let mut ws = Workspace::new("", constraints.clone(), &mut txr);
+      let mut ws = Workspace::new("", constraints.clone(), &mut txr).unwrap();
 
       let mut nodes = vec![];
       let mut atoms = vec![];
@@ -473,7 +1539,7 @@ mod tests {
         let atom = rng.gen();
         let i = rng.gen_range(0..nodes.len());
         let label = rng.gen_range(0..K * 2);
-        ws.set_atom(&txr, atom, Some((nodes[i].0, label, vec![].into())));
+        ws.set_atom(&txr, atom, Some((nodes[i].0, label, vec![].into()))).unwrap();
         if label < K {
           nodes[i].1.push((atom, label));
         }
@@ -494,7 +1560,7 @@ mod tests {
       }
 
       // Done.
-      ws.barrier(&mut txr);
+      ws.barrier(&mut txr).unwrap();
 
       // Generate operations.
       for _ in 0..round {
@@ -522,7 +1588,7 @@ mod tests {
             if rng.gen_ratio(1, 16) {
               atom = rng.gen();
             }
-            let mut value = ws.atom(&txr, atom);
+            let mut value = ws.atom(&txr, atom).unwrap();
             if rng.gen_ratio(1, 16) {
               value = None;
             }
@@ -540,7 +1606,7 @@ mod tests {
                 inner.1 = rng.gen();
               }
             }
-            ws.set_atom(&txr, atom, value);
+            ws.set_atom(&txr, atom, value).unwrap();
           }
           2 => {
             // Randomly mutate edge.
@@ -576,12 +1642,12 @@ mod tests {
       }
 
       // Done.
-      ws.barrier(&mut txr);
+      ws.barrier(&mut txr).unwrap();
 
       // Check invariants.
       // (1)
       for atom in atoms {
-        if let Some((src, _, _)) = ws.atom(&txr, atom) {
+        if let Some((src, _, _)) = ws.atom(&txr, atom).unwrap() {
           assert!(ws.node(&txr, src).is_some());
         }
       }
@@ -597,7 +1663,7 @@ mod tests {
       for (node, ratoms, redges) in nodes {
         if ws.node(&txr, node).is_some() {
           for (ratom, label) in ratoms {
-            assert_eq!(ws.atom(&txr, ratom).map(|(src, label, _)| (src, label)), Some((node, label)));
+            assert_eq!(ws.atom(&txr, ratom).unwrap().map(|(src, label, _)| (src, label)), Some((node, label)));
           }
           for (redge, label) in redges {
             assert_eq!(ws.edge(&txr, redge).map(|(src, label, _)| (src, label)), Some((node, label)));
@@ -616,7 +1682,7 @@ mod tests {
     let mut constraints = Constraints::new();
     constraints.add_sticky_edge(0);
     constraints.add_acyclic_edge(0);
-    let mut ws = Workspace::new("", constraints, &mut txr);
+    let mut ws = Workspace::new("", constraints, &mut txr).unwrap();
 
     let node0 = rng.gen();
     let node1 = rng.gen();
@@ -633,7 +1699,7 @@ mod tests {
     ws.set_edge(&txr, edge0, Some((node0, 0, node1)));
     ws.set_edge(&txr, edge1, Some((node1, 0, node2)));
     ws.set_edge(&txr, edge2, Some((node2, 0, node3)));
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert!(ws.node(&txr, node0).is_some());
     assert!(ws.node(&txr, node1).is_some());
     assert!(ws.node(&txr, node2).is_some());
@@ -643,7 +1709,7 @@ mod tests {
     assert!(ws.edge(&txr, edge2).is_some());
 
     ws.set_edge(&txr, edge3, Some((node2, 0, node0)));
-    ws.barrier(&mut txr);
+    ws.barrier(&mut txr).unwrap();
     assert!(ws.node(&txr, node0).is_none());
     assert!(ws.node(&txr, node1).is_none());
     assert!(ws.node(&txr, node2).is_none());
@@ -652,4 +1718,1201 @@ mod tests {
     assert!(ws.edge(&txr, edge1).is_none());
     assert!(ws.edge(&txr, edge2).is_none());
   }
+
+  #[test]
+  fn edge_by_label_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+
+    let node0 = rng.gen();
+    let node1 = rng.gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_node(&txr, node1, Some(0));
+    let edge0 = rng.gen();
+    let edge1 = rng.gen();
+    let edge2 = rng.gen();
+    ws.set_edge(&txr, edge0, Some((node0, 1, node1)));
+    ws.set_edge(&txr, edge1, Some((node1, 1, node0)));
+    ws.set_edge(&txr, edge2, Some((node0, 2, node1)));
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(
+      ws.edge_id_src_dst_by_label(&txr, 1),
+      BTreeMap::from([(edge0, (node0, node1)), (edge1, (node1, node0))])
+    );
+
+    ws.set_edge(&txr, edge0, None);
+    ws.set_edge(&txr, edge2, Some((node1, 1, node1)));
+    assert_eq!(
+      ws.edge_id_src_dst_by_label(&txr, 1),
+      BTreeMap::from([(edge1, (node1, node0)), (edge2, (node1, node1))])
+    );
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(
+      ws.edge_id_src_dst_by_label(&txr, 1),
+      BTreeMap::from([(edge1, (node1, node0)), (edge2, (node1, node1))])
+    );
+    assert_eq!(ws.edge_id_src_dst_by_label(&txr, 2), BTreeMap::new());
+    let mut group = vec![(edge1, node0), (edge2, node1)];
+    group.sort();
+    assert_eq!(ws.edge_label_id_dst_by_src(&txr, node1), BTreeMap::from([(1, group)]));
+    assert_eq!(ws.edge_label_id_dst_by_src(&txr, node0), BTreeMap::new());
+  }
+
+  #[test]
+  fn discard_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+
+    let node0 = rng.gen();
+    let node1 = rng.gen();
+    let atom0 = rng.gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws.barrier(&mut txr).unwrap();
+    let version = ws.sync_version(&txr);
+
+    ws.set_node(&txr, node1, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![2].into()))).unwrap();
+    assert_eq!(ws.node(&txr, node1), Some(0));
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node0, 1, vec![2].into())));
+    ws.discard();
+    assert_eq!(ws.node(&txr, node1), None);
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws.sync_version(&txr), version);
+    assert!(ws.barrier(&mut txr).unwrap().is_empty());
+
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![3].into()))).unwrap();
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node0, 1, vec![3].into())));
+  }
+
+  #[test]
+  fn sync_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    let node0 = rng.gen();
+    let node1 = rng.gen();
+    let atom0 = rng.gen();
+    let edge0 = rng.gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws1.set_node(&txr1, node1, Some(0));
+    ws1.barrier(&mut txr1).unwrap();
+
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 2, applied_override: 0, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    let (bucket, clock, _) = ws0.nodes.get(&txr0, node0).unwrap();
+    let ignored0 = Ignored { name: NODES_NAME, id: node0, bucket, clock, outcome: Outcome::RejectedTie };
+    let (bucket, clock, _) = ws0.atoms.get(&txr0, atom0).unwrap().unwrap();
+    let ignored1 = Ignored { name: ATOMS_NAME, id: atom0, bucket, clock, outcome: Outcome::RejectedTie };
+    let ignored = vec![ignored0, ignored1];
+    assert_eq!(stats, SyncStats { applied_new: 0, applied_override: 0, ignored_older: 2, unknown_own: 0, ignored });
+    ws1.set_edge(&txr1, edge0, Some((node1, 2, node0)));
+    ws1.barrier(&mut txr1).unwrap();
+    let actions = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    ws0.sync_join(&txr0, &actions).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+
+    for (ws, txr) in [(&ws0, &txr0), (&ws1, &txr1)] {
+      assert_eq!(ws.node(txr, node0), Some(0));
+      assert_eq!(ws.node(txr, node1), Some(0));
+      assert_eq!(ws.atom(txr, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+      assert_eq!(ws.edge(txr, edge0), Some((node1, 2, node0)));
+    }
+
+    let mut corrupted = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap().into_vec();
+    let len = corrupted.len();
+    corrupted[len / 2] ^= 1;
+    assert!(matches!(ws1.sync_join(&txr1, &corrupted), Err(StoreError::ChecksumMismatch)));
+    assert!(matches!(ws1.sync_join(&txr1, &corrupted[..len - 1]), Err(StoreError::ChecksumMismatch)));
+    assert!(matches!(ws0.sync_actions(&txr0, &[]), Err(StoreError::InvalidHeader)));
+  }
+
+  #[test]
+  fn bulk_get_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+
+    const N: usize = 1234;
+    let nodes: Vec<u128> = (0..N).map(|_| rng.gen()).collect();
+    let atoms: Vec<u128> = (0..N).map(|_| rng.gen()).collect();
+    let edges: Vec<u128> = (0..N).map(|_| rng.gen()).collect();
+    for i in 0..N {
+      ws.set_node(&txr, nodes[i], Some(i as u64));
+      ws.set_atom(&txr, atoms[i], Some((nodes[i], 0, vec![i as u8].into()))).unwrap();
+      ws.set_edge(&txr, edges[i], Some((nodes[i], 0, nodes[N - 1 - i])));
+    }
+    ws.barrier(&mut txr).unwrap();
+    ws.set_node(&txr, nodes[0], None);
+    ws.set_atom(&txr, atoms[1], Some((nodes[1], 1, vec![].into()))).unwrap();
+    ws.set_edge(&txr, edges[2], None);
+
+    let mut ids = nodes.clone();
+    ids.push(rng.gen());
+    let expected = ids.iter().filter_map(|&id| Some((id, ws.node(&txr, id)?))).collect::<BTreeMap<_, _>>();
+    assert_eq!(expected.len(), N - 1);
+    assert_eq!(ws.nodes(&txr, &ids), expected);
+    let mut ids = atoms.clone();
+    ids.push(rng.gen());
+    let expected = ids.iter().filter_map(|&id| Some((id, ws.atom(&txr, id).unwrap()?))).collect::<BTreeMap<_, _>>();
+    assert_eq!(expected.len(), N);
+    assert_eq!(ws.atoms(&txr, &ids).unwrap(), expected);
+    let mut ids = edges.clone();
+    ids.push(rng.gen());
+    let expected = ids.iter().filter_map(|&id| Some((id, ws.edge(&txr, id)?))).collect::<BTreeMap<_, _>>();
+    assert_eq!(expected.len(), N - 1);
+    assert_eq!(ws.edges(&txr, &ids), expected);
+  }
+
+  #[test]
+  fn clock_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+
+    let node0 = rng.gen();
+    let atom0 = rng.gen();
+    let edge0 = rng.gen();
+    assert_eq!(ws.node_clock(&txr, node0), None);
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 0, vec![].into()))).unwrap();
+    ws.set_edge(&txr, edge0, Some((node0, 0, node0)));
+    let (bucket, clock) = ws.node_clock(&txr, node0).unwrap();
+    assert_eq!(bucket, ws.this());
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.node_clock(&txr, node0), Some((bucket, clock)));
+    assert!(ws.atom_clock(&txr, atom0).unwrap().unwrap() > (bucket, clock));
+    assert!(ws.edge_clock(&txr, edge0).unwrap() > (bucket, clock));
+
+    ws.set_node(&txr, node0, None);
+    let (bucket_, clock_) = ws.node_clock(&txr, node0).unwrap();
+    assert_eq!(bucket_, ws.this());
+    assert!(clock_ > clock);
+  }
+
+  #[test]
+  fn clock_source_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let node0 = rand::thread_rng().gen();
+
+    // A purely logical clock, which follows clock values seen from others.
+    ws0.set_clock(|min| min);
+    ws0.set_node(&txr0, node0, Some(0));
+    assert_eq!(ws0.node_clock(&txr0, node0), Some((ws0.this(), 0)));
+    ws0.set_node(&txr0, node0, Some(1));
+    assert_eq!(ws0.node_clock(&txr0, node0), Some((ws0.this(), 1)));
+    ws0.barrier(&mut txr0).unwrap();
+
+    ws1.sync_join(&txr1, &ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap()).unwrap();
+    ws1.set_node(&txr1, node0, Some(2));
+    ws1.barrier(&mut txr1).unwrap();
+    let (_, clock) = ws1.node_clock(&txr1, node0).unwrap();
+    assert!(clock > 1);
+    ws0.sync_join(&txr0, &ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap()).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_node(&txr0, node0, Some(3));
+    assert_eq!(ws0.node_clock(&txr0, node0), Some((ws0.this(), clock + 1)));
+
+    // Values going backwards are raised.
+    ws0.set_clock(|_| 0);
+    ws0.set_node(&txr0, node0, Some(4));
+    assert_eq!(ws0.node_clock(&txr0, node0), Some((ws0.this(), clock + 2)));
+  }
+
+  #[test]
+  fn join_outcome_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+
+    let item = |bucket, clock, value: u8| (bucket, clock, Some((0, 0, vec![value].into())));
+    ws.atoms.set(&txr, 1, 1, 5, item(1, 5, 0).2).unwrap();
+    ws.atoms.save(&mut txr).unwrap();
+    ws.atoms.set(&txr, 2, 1, 6, item(1, 6, 0).2).unwrap();
+
+    let outcomes =
+      ws.atoms.join(&txr, [(1, item(2, 4, 1)), (1, item(1, 5, 1)), (2, item(2, 7, 1)), (3, item(2, 8, 1))]).unwrap();
+    assert_eq!(
+      outcomes,
+      [(1, Outcome::RejectedOlder), (1, Outcome::RejectedTie), (2, Outcome::Applied), (3, Outcome::Inserted)]
+    );
+    assert_eq!(ws.atom(&txr, 1).unwrap(), Some((0, 0, vec![0].into())));
+    assert_eq!(ws.atom(&txr, 2).unwrap(), Some((0, 0, vec![1].into())));
+
+    // Newer than the current item, but clock value for bucket 2 has already been seen.
+    let outcomes = ws.atoms.join(&txr, [(1, item(2, 6, 1)), (1, item(3, 6, 1))]).unwrap();
+    assert_eq!(outcomes, [(1, Outcome::RejectedOlder), (1, Outcome::Applied)]);
+  }
+
+  #[test]
+  fn recover_simple() {
+    use super::atom_set::AtomSetTransactor;
+
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    ws.set_node(&txr, 1, Some(0));
+    ws.set_atom(&txr, 2, Some((1, 0, vec![].into()))).unwrap();
+    ws.barrier(&mut txr).unwrap();
+    assert!(!ws.recover(&mut txr).unwrap());
+
+    // Simulate a data write whose metadata update was lost.
+    let (bucket, clock) = ws.atom_clock(&txr, 2).unwrap().unwrap();
+    AtomSetTransactor::set(&mut txr, "", ATOMS_NAME, 3, (bucket, clock + 10, Some((1, 0, vec![].into())))).unwrap();
+    AtomSetTransactor::set(&mut txr, "", ATOMS_NAME, 4, (233, 1, None)).unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    assert_eq!(ws.atoms.buckets(), BTreeMap::from([(bucket, clock)]));
+    assert!(ws.recover(&mut txr).unwrap());
+    assert_eq!(ws.atoms.buckets(), BTreeMap::from([(bucket, clock + 10), (233, 1)]));
+    assert!(!ws.recover(&mut txr).unwrap());
+    let ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    assert_eq!(ws.atoms.buckets(), BTreeMap::from([(bucket, clock + 10), (233, 1)]));
+  }
+
+  #[test]
+  fn actions_touching_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    let [node0, node1, node2, node3]: [u128; 4] = rng.gen();
+    let [atom0, atom1, edge0, edge1]: [u128; 4] = rng.gen();
+    for node in [node0, node1, node2, node3] {
+      ws0.set_node(&txr0, node, Some(0));
+    }
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws0.set_atom(&txr0, atom1, Some((node2, 1, vec![2].into()))).unwrap();
+    ws0.set_edge(&txr0, edge0, Some((node1, 2, node0)));
+    ws0.set_edge(&txr0, edge1, Some((node1, 2, node2)));
+    ws0.barrier(&mut txr0).unwrap();
+
+    let actions = ws0.sync_actions_touching(&txr0, atom0, 1).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 4, applied_override: 0, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.node(&txr1, node0), Some(0));
+    assert_eq!(ws1.node(&txr1, node1), Some(0));
+    assert_eq!(ws1.node(&txr1, node2), None);
+    assert_eq!(ws1.node(&txr1, node3), None);
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws1.atom(&txr1, atom1).unwrap(), None);
+    assert_eq!(ws1.edge(&txr1, edge0), Some((node1, 2, node0)));
+    assert_eq!(ws1.edge(&txr1, edge1), None);
+
+    let actions = ws0.sync_actions_touching(&txr0, node0, 2).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.node(&txr1, node2), Some(0));
+    assert_eq!(ws1.node(&txr1, node3), None);
+    assert_eq!(ws1.atom(&txr1, atom1).unwrap(), Some((node2, 1, vec![2].into())));
+    assert_eq!(ws1.edge(&txr1, edge1), Some((node1, 2, node2)));
+  }
+
+  #[test]
+  fn sync_for_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    let [node0, node1, atom0, edge0]: [u128; 4] = rng.gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_node(&txr0, node1, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws0.set_edge(&txr0, edge0, Some((node0, 2, node1)));
+    ws0.barrier(&mut txr0).unwrap();
+
+    // Atoms only, with nodes coming along.
+    let actions = ws0.sync_actions_for(&txr0, &ws1.sync_version_for(&txr1, &[ATOMS_NAME])).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 3, applied_override: 0, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws1.edge(&txr1, edge0), None);
+
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![2].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    let actions = ws0.sync_actions_for(&txr0, &ws1.sync_version_for(&txr1, &[EDGES_NAME])).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 1, applied_override: 0, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws1.edge(&txr1, edge0), Some((node0, 2, node1)));
+
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 0, applied_override: 1, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![2].into())));
+  }
+
+  #[test]
+  fn create_if_absent_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    let [node0, node1, atom0, edge0]: [u128; 4] = rng.gen();
+    assert!(ws0.create_node_if_absent(&txr0, node0, 1));
+    assert!(!ws0.create_node_if_absent(&txr0, node0, 2));
+    assert!(ws0.create_atom_if_absent(&txr0, atom0, (node0, 1, vec![1].into())).unwrap());
+    ws0.barrier(&mut txr0).unwrap();
+    assert!(!ws0.create_atom_if_absent(&txr0, atom0, (node0, 1, vec![2].into())).unwrap());
+    assert_eq!(ws0.node(&txr0, node0), Some(1));
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+
+    // Synced data is kept.
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    assert!(!ws1.create_node_if_absent(&txr1, node0, 3));
+    assert!(!ws1.create_atom_if_absent(&txr1, atom0, (node0, 1, vec![3].into())).unwrap());
+    assert!(ws1.create_node_if_absent(&txr1, node1, 3));
+    assert!(ws1.create_edge_if_absent(&txr1, edge0, (node0, 2, node1)));
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws1.edge(&txr1, edge0), Some((node0, 2, node1)));
+
+    // Removed ones are not brought back.
+    ws1.set_edge(&txr1, edge0, None);
+    ws1.barrier(&mut txr1).unwrap();
+    assert!(!ws1.create_edge_if_absent(&txr1, edge0, (node0, 2, node1)));
+    assert_eq!(ws1.edge(&txr1, edge0), None);
+  }
+
+  #[test]
+  fn coalesce_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    // Writes to the same id between barriers end up as a single saved item.
+    let [node0, atom0]: [u128; 2] = rng.gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    for i in 0..100 {
+      ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![i].into()))).unwrap();
+      assert_eq!(ws0.atom(&txr0, atom0).unwrap(), Some((node0, 1, vec![i].into())));
+    }
+    assert_eq!(ws0.atoms.mods().len(), 1);
+    let events = ws0.barrier(&mut txr0).unwrap();
+    assert_eq!(events.len(), 2);
+
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 2, applied_override: 0, ignored_older: 0, unknown_own: 0, ignored: vec![] }
+    );
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![99].into())));
+  }
+
+  #[test]
+  fn debounce_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    ws.set_debounce(1, Some(Duration::from_secs(3600)));
+    ws.set_debounce(2, Some(Duration::ZERO));
+    let [node0, atom0, atom1, atom2]: [u128; 4] = rand::thread_rng().gen();
+    ws.set_node(&txr, node0, Some(0));
+    assert_eq!(ws.barrier(&mut txr).unwrap().len(), 1);
+
+    // Held writes are visible, but not saved or reported by barriers.
+    for i in 0..10 {
+      ws.set_atom(&txr, atom0, Some((node0, 1, vec![i].into()))).unwrap();
+      ws.set_atom(&txr, atom2, Some((node0, 2, vec![i].into()))).unwrap();
+      let events = ws.barrier(&mut txr).unwrap();
+      assert_eq!(events.len(), 1);
+      assert!(matches!(events[0], CEventData::Atom { id, .. } if id == atom2.into()));
+      assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node0, 1, vec![i].into())));
+      assert_eq!(ws.atom_id_value_by_src_label(&txr, node0, 1).unwrap().len(), 1);
+    }
+    assert_eq!(Workspace::new("", Constraints::new(), &mut txr).unwrap().atom(&txr, atom0).unwrap(), None);
+    // Flushing saves only the latest value, as a single write and undo step.
+    let events = ws.flush(&mut txr).unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], CEventData::Atom { id, .. } if *id == atom0.into()));
+    assert_eq!(Workspace::new("", Constraints::new(), &mut txr).unwrap().atom(&txr, atom0).unwrap().unwrap().2[0], 9);
+    assert!(ws.flush(&mut txr).unwrap().is_empty());
+    ws.undo(&mut txr).unwrap();
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), None);
+    ws.set_atom(&txr, atom1, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.discard();
+    assert_eq!(ws.atom(&txr, atom1).unwrap(), None);
+    assert!(ws.flush(&mut txr).unwrap().is_empty());
+
+    // Removals and writes to other labels are not held.
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_atom(&txr, atom0, None).unwrap();
+    assert_eq!(ws.barrier(&mut txr).unwrap().len(), 0);
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_atom(&txr, atom0, Some((node0, 3, vec![0].into()))).unwrap();
+    assert_eq!(ws.barrier(&mut txr).unwrap().len(), 1);
+
+    // Constraint fix-ups override held writes.
+    ws.set_atom(&txr, atom1, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_node(&txr, node0, None);
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.atom(&txr, atom1).unwrap(), None);
+    assert!(ws.flush(&mut txr).unwrap().is_empty());
+  }
+
+  #[test]
+  fn sync_deterministic() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr2: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let mut ws2 = Workspace::new("", Constraints::new(), &mut txr2).unwrap();
+
+    for _ in 0..10 {
+      let [node, atom, edge]: [u128; 3] = rng.gen();
+      ws0.set_node(&txr0, node, Some(0));
+      ws0.set_atom(&txr0, atom, Some((node, 1, vec![rng.gen()].into()))).unwrap();
+      ws0.set_edge(&txr0, edge, Some((node, 2, node)));
+    }
+    ws0.barrier(&mut txr0).unwrap();
+
+    // Same state gives the same bytes, also on a replica that got it by sync.
+    let version = ws2.sync_version(&txr2);
+    let actions = ws0.sync_actions(&txr0, &version).unwrap();
+    assert_eq!(actions, ws0.sync_actions(&txr0, &version).unwrap());
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(actions, ws1.sync_actions(&txr1, &version).unwrap());
+
+    // Same contents joined in opposite orders also give the same bytes.
+    for _ in 0..10 {
+      let node = rng.gen();
+      ws1.set_node(&txr1, node, Some(0));
+      ws1.set_atom(&txr1, rng.gen(), Some((node, 1, vec![rng.gen()].into()))).unwrap();
+    }
+    ws1.barrier(&mut txr1).unwrap();
+    let actions1 = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    ws0.sync_join(&txr0, &actions1).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws2.sync_join(&txr2, &ws1.sync_actions(&txr1, &version).unwrap()).unwrap();
+    ws2.sync_join(&txr2, &actions).unwrap();
+    ws2.barrier(&mut txr2).unwrap();
+    assert_eq!(ws0.sync_actions(&txr0, &version).unwrap(), ws2.sync_actions(&txr2, &version).unwrap());
+  }
+
+  #[test]
+  fn reassign_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut constraints = Constraints::new();
+    constraints.add_sticky_atom(1);
+    let mut ws = Workspace::new("", constraints, &mut txr).unwrap();
+
+    let [node0, node1, node2, atom0, edge0, edge1, edge2]: [u128; 7] = rng.gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_node(&txr, node1, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws.set_edge(&txr, edge0, Some((node0, 2, node1)));
+    ws.set_edge(&txr, edge1, Some((node1, 2, node0)));
+    ws.set_edge(&txr, edge2, Some((node0, 2, node0)));
+    ws.barrier(&mut txr).unwrap();
+
+    assert!(!ws.reassign_node(&txr, node0, node1).unwrap());
+    assert!(!ws.reassign_node(&txr, node2, rng.gen()).unwrap());
+    assert!(ws.reassign_node(&txr, node0, node2).unwrap());
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.node(&txr, node0), None);
+    assert_eq!(ws.node(&txr, node2), Some(0));
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node2, 1, vec![1].into())));
+    assert_eq!(ws.edge(&txr, edge0), Some((node2, 2, node1)));
+    assert_eq!(ws.edge(&txr, edge1), Some((node1, 2, node2)));
+    assert_eq!(ws.edge(&txr, edge2), Some((node2, 2, node2)));
+
+    // The old id stays taken.
+    assert!(!ws.reassign_node(&txr, node2, node0).unwrap());
+  }
+
+  #[test]
+  fn duplicate_replica() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr2: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    use metadata::WorkspaceMetadataTransactor;
+    txr2.init_this("");
+    txr2.put_this("", ws0.this());
+    let mut ws2 = Workspace::new("", Constraints::new(), &mut txr2).unwrap();
+    assert_eq!(ws2.this(), ws0.this());
+
+    let [node0, node1]: [u128; 2] = rng.gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.barrier(&mut txr0).unwrap();
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+
+    // Own actions coming back are fine, even when rejecting unknown ones.
+    ws0.set_reject_unknown_own(true);
+    let actions = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    assert_eq!(ws0.sync_join(&txr0, &actions).unwrap(), SyncStats::default());
+    let actions = ws1.sync_actions(&txr1, &ws2.sync_version(&txr2)).unwrap();
+    assert!(ws0.sync_join(&txr0, &actions).is_ok());
+    ws0.set_reject_unknown_own(false);
+
+    // Unknown ones under the same ID are rejected as a whole if asked to.
+    ws2.set_reject_unknown_own(true);
+    assert!(matches!(ws2.sync_join(&txr2, &actions), Err(StoreError::DuplicateReplica)));
+    assert!(matches!(ws2.sync_join_with(&txr2, &actions, |_| {}), Err(StoreError::DuplicateReplica)));
+    assert!(ws2.barrier(&mut txr2).unwrap().is_empty());
+    ws2.set_reject_unknown_own(false);
+
+    // Otherwise, they are joined, but counted.
+    let stats = ws2.sync_join(&txr2, &actions).unwrap();
+    assert_eq!(
+      stats,
+      SyncStats { applied_new: 1, applied_override: 0, ignored_older: 0, unknown_own: 1, ignored: vec![] }
+    );
+    ws2.barrier(&mut txr2).unwrap();
+    assert_eq!(ws2.node(&txr2, node0), Some(0));
+    assert_eq!(ws2.sync_version(&txr2), ws0.sync_version(&txr0));
+    ws2.set_node(&txr2, node1, Some(0));
+    ws2.barrier(&mut txr2).unwrap();
+    let actions = ws2.sync_actions(&txr2, &ws0.sync_version(&txr0)).unwrap();
+    assert_eq!(ws0.sync_join(&txr0, &actions).unwrap().unknown_own, 1);
+    ws0.barrier(&mut txr0).unwrap();
+    assert_eq!(ws0.node(&txr0, node1), Some(0));
+  }
+
+  #[test]
+  fn set_edge_endpoints() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, node1, node2, edge0, edge1]: [u128; 5] = rand::thread_rng().gen();
+    for node in [node0, node1, node2] {
+      ws.set_node(&txr, node, Some(0));
+    }
+    ws.set_edge(&txr, edge0, Some((node0, 1, node1)));
+    ws.set_edge_src(&txr, edge0, node2);
+    assert_eq!(ws.edge(&txr, edge0), Some((node2, 1, node1)));
+    ws.set_edge_dst(&txr, edge0, node0);
+    assert_eq!(ws.edge(&txr, edge0), Some((node2, 1, node0)));
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.edge(&txr, edge0), Some((node2, 1, node0)));
+
+    ws.set_edge_src(&txr, edge1, node0);
+    ws.set_edge_dst(&txr, edge1, node0);
+    assert_eq!(ws.edge_clock(&txr, edge1), None);
+  }
+
+  #[test]
+  fn typed_atoms() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, atom0, atom1, atom2, atom3]: [u128; 5] = rand::thread_rng().gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_atom_u64(&txr, atom0, Some((node0, 1, 0x0102030405060708))).unwrap();
+    ws.set_atom_str(&txr, atom1, Some((node0, 2, "hello"))).unwrap();
+    ws.set_atom_bool(&txr, atom2, Some((node0, 3, true))).unwrap();
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.atom(&txr, atom0).unwrap().unwrap().2.as_ref(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(ws.atom_u64(&txr, atom0).unwrap(), Some((node0, 1, 0x0102030405060708)));
+    assert_eq!(ws.atom_str(&txr, atom1).unwrap(), Some((node0, 2, "hello".to_owned())));
+    assert_eq!(ws.atom_bool(&txr, atom2).unwrap(), Some((node0, 3, true)));
+    assert_eq!(ws.atom_u64(&txr, atom3).unwrap(), None);
+
+    assert!(matches!(
+      ws.atom_u64(&txr, atom1),
+      Err(StoreError::Decode(DecodeError::InvalidLength { expected: 8, found: 5 }))
+    ));
+    assert!(matches!(
+      ws.atom_bool(&txr, atom1),
+      Err(StoreError::Decode(DecodeError::InvalidLength { expected: 1, found: 5 }))
+    ));
+    ws.set_atom(&txr, atom3, Some((node0, 4, vec![2].into()))).unwrap();
+    assert!(matches!(ws.atom_bool(&txr, atom3), Err(StoreError::Decode(DecodeError::InvalidBool(2)))));
+    ws.set_atom(&txr, atom3, Some((node0, 4, vec![0xff].into()))).unwrap();
+    assert!(matches!(ws.atom_str(&txr, atom3), Err(StoreError::Decode(DecodeError::InvalidUtf8))));
+
+    ws.set_atom_bool(&txr, atom2, None).unwrap();
+    assert_eq!(ws.atom_bool(&txr, atom2).unwrap(), None);
+  }
+
+  #[test]
+  fn sync_conflict() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+
+    // Offline edits on both sides, the later one winning.
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws1.set_node(&txr1, node0, Some(0));
+    ws1.set_atom(&txr1, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+
+    let mut conflicts = Vec::new();
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    let stats = ws1.sync_join_with(&txr1, &actions, |conflict| conflicts.push(conflict)).unwrap();
+    assert_eq!(stats.ignored_older, 2);
+    let dropped = (ws0.this(), ws0.atom_clock(&txr0, atom0).unwrap().unwrap().1, Some((node0, 1, vec![0].into())));
+    let winning = (ws1.this(), ws1.atom_clock(&txr1, atom0).unwrap().unwrap().1, Some((node0, 1, vec![1].into())));
+    assert_eq!(conflicts.len(), 2);
+    assert!(matches!(conflicts[0], Conflict::Node { id, .. } if id == node0));
+    assert_eq!(conflicts[1], Conflict::Atom { id: atom0, dropped, winning: Some(winning) });
+
+    // Nothing is reported the other way round, or for repeated actions.
+    let mut conflicts = Vec::new();
+    let actions = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    let stats = ws0.sync_join_with(&txr0, &actions, |conflict| conflicts.push(conflict)).unwrap();
+    assert_eq!(stats.applied_override, 2);
+    ws0.barrier(&mut txr0).unwrap();
+    let stats = ws0.sync_join_with(&txr0, &actions, |conflict| conflicts.push(conflict)).unwrap();
+    assert_eq!(stats.ignored_older, 2);
+    assert_eq!(conflicts, vec![]);
+  }
+
+  #[test]
+  fn sync_chunk_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+
+    let nodes: [u128; 20] = rng.gen();
+    for (i, &node) in nodes.iter().enumerate() {
+      ws0.set_node(&txr0, node, Some(0));
+      ws0.set_atom(&txr0, rng.gen(), Some((node, 1, vec![0; 100].into()))).unwrap();
+      ws0.set_edge(&txr0, rng.gen(), Some((node, 2, nodes[(i + 1) % nodes.len()])));
+    }
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_node(&txr0, nodes[0], Some(1));
+
+    let version = ws1.sync_version(&txr1);
+    let mut cursor = Some(version.clone());
+    let mut chunks = 0;
+    while let Some(curr) = cursor {
+      let actions;
+      (actions, cursor) = ws0.sync_actions_chunk(&txr0, &curr, 500).unwrap();
+      assert!(actions.len() < 1000);
+      ws1.sync_join(&txr1, &actions).unwrap();
+      ws1.barrier(&mut txr1).unwrap();
+      chunks += 1;
+    }
+    assert!(chunks > 3);
+    assert_eq!(ws1.sync_version(&txr1), ws0.sync_version(&txr0));
+    let stats = ws1.sync_join(&txr1, &ws0.sync_actions(&txr0, &version).unwrap()).unwrap();
+    assert_eq!(stats.applied_new + stats.applied_override, 0);
+    assert_eq!(ws1.node(&txr1, nodes[0]), Some(1));
+
+    // Everything fits at once.
+    let (actions, cursor) = ws0.sync_actions_chunk(&txr0, &version, usize::MAX).unwrap();
+    assert_eq!(cursor, None);
+    assert_eq!(actions, ws0.sync_actions(&txr0, &version).unwrap());
+  }
+
+  #[test]
+  fn sync_idempotent() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, node1, atom0, edge0]: [u128; 4] = rand::thread_rng().gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_node(&txr0, node1, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.set_edge(&txr0, edge0, Some((node0, 2, node1)));
+    ws0.barrier(&mut txr0).unwrap();
+
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    assert_eq!(ws1.barrier(&mut txr1).unwrap().len(), 4);
+    let version = ws1.sync_version(&txr1);
+
+    // Redelivered, e.g. after a lost acknowledgement.
+    let stats = ws1.sync_join(&txr1, &actions).unwrap();
+    let ignored = stats.ignored.iter().map(|ignored| (ignored.name, ignored.id, ignored.outcome)).collect::<Vec<_>>();
+    assert_eq!(
+      ignored,
+      [
+        (NODES_NAME, node0, Outcome::RejectedTie),
+        (NODES_NAME, node1, Outcome::RejectedTie),
+        (ATOMS_NAME, atom0, Outcome::RejectedTie),
+        (EDGES_NAME, edge0, Outcome::RejectedTie),
+      ]
+    );
+    let ignored = stats.ignored.clone();
+    assert_eq!(stats, SyncStats { applied_new: 0, applied_override: 0, ignored_older: 4, unknown_own: 0, ignored });
+    assert!(ws1.nodes.mods().is_empty() && ws1.atoms.mods().is_empty() && ws1.edges.mods().is_empty());
+    assert!(ws1.barrier(&mut txr1).unwrap().is_empty());
+    assert_eq!(ws1.sync_version(&txr1), version);
+  }
+
+  #[test]
+  fn remove_tombstones_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr2: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let ws2 = Workspace::new("", Constraints::new(), &mut txr2).unwrap();
+    let [node0, node1, atom0, atom1, edge0]: [u128; 5] = rand::thread_rng().gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_node(&txr0, node1, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.set_edge(&txr0, edge0, Some((node0, 2, node1)));
+    ws0.barrier(&mut txr0).unwrap();
+    let old = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws0.set_node(&txr0, node1, None);
+    ws0.set_atom(&txr0, atom0, None).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    assert!(ws0.edge_clock(&txr0, edge0).is_some() && ws0.edge(&txr0, edge0).is_none());
+
+    // Peer 1 has seen everything, peer 2 has not.
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    let (version1, version2) = (ws1.sync_version(&txr1), ws2.sync_version(&txr2));
+    assert_eq!(ws0.remove_tombstones(&mut txr0, &[]).unwrap(), 0);
+    assert_eq!(ws0.remove_tombstones(&mut txr0, &[&version1, &version2]).unwrap(), 0);
+    assert!(ws0.edge_clock(&txr0, edge0).is_some());
+
+    // Pending removals are kept.
+    ws0.set_atom(&txr0, atom1, Some((node0, 1, vec![1].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_atom(&txr0, atom1, None).unwrap();
+    assert_eq!(ws0.remove_tombstones(&mut txr0, &[&version1]).unwrap(), 3);
+    assert_eq!(ws0.node_clock(&txr0, node1), None);
+    assert_eq!(ws0.atom_clock(&txr0, atom0).unwrap(), None);
+    assert_eq!(ws0.edge_clock(&txr0, edge0), None);
+    assert!(ws0.atom_clock(&txr0, atom1).unwrap().is_some());
+    assert_eq!(ws0.node(&txr0, node0), Some(0));
+
+    // Old actions cannot bring them back.
+    let stats = ws0.sync_join(&txr0, &old).unwrap();
+    assert_eq!(stats.applied_new, 0);
+    assert_eq!(ws0.node(&txr0, node1), None);
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), None);
+  }
+
+  #[test]
+  fn undo_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, atom0, atom1]: [u128; 3] = rand::thread_rng().gen();
+    let value = |v: u8| Some((node0, 1, vec![v].into()));
+
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, value(0)).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_atom(&txr0, atom0, value(1)).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_atom(&txr0, atom0, value(2)).unwrap();
+
+    // Pending changes form their own step.
+    assert_eq!(ws0.undo(&mut txr0).unwrap().len(), 2);
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(1));
+    ws0.undo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(0));
+    ws0.redo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(1));
+    ws0.redo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(2));
+    assert!(ws0.redo(&mut txr0).unwrap().is_empty());
+
+    // Undoing is synced as a normal write.
+    ws0.undo(&mut txr0).unwrap();
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), value(1));
+
+    // New local changes discard redo steps.
+    ws0.set_atom(&txr0, atom1, value(3)).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    assert!(ws0.redo(&mut txr0).unwrap().is_empty());
+    ws0.undo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom1).unwrap(), None);
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(1));
+
+    // Items overwritten by peers are left alone.
+    ws1.set_atom(&txr1, atom0, value(4)).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    let actions = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    ws0.sync_join(&txr0, &actions).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    assert!(ws0.undo(&mut txr0).unwrap().is_empty());
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), value(4));
+
+    // Undoing node creation also removes what was attached.
+    ws0.undo(&mut txr0).unwrap();
+    assert_eq!(ws0.node(&txr0, node0), None);
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), None);
+  }
+
+  #[test]
+  fn undo_after_join() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, node1, atom0, atom1]: [u128; 4] = rand::thread_rng().gen();
+
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_node(&txr0, node1, Some(0));
+    ws0.set_atom(&txr0, atom1, Some((node1, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.set_atom(&txr0, atom1, Some((node1, 1, vec![1].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws0.undo(&mut txr0).unwrap();
+
+    // Removing `atom0` after a peer removes its node is not an undo step, and
+    // keeps redo steps.
+    ws1.set_node(&txr1, node0, None);
+    ws1.barrier(&mut txr1).unwrap();
+    let actions = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    ws0.sync_join(&txr0, &actions).unwrap();
+    assert_eq!(ws0.barrier(&mut txr0).unwrap().len(), 2);
+    assert_eq!(ws0.atom_clock(&txr0, atom0).unwrap().unwrap().0, ws0.this());
+    ws0.redo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom1).unwrap(), Some((node1, 1, vec![1].into())));
+    ws0.undo(&mut txr0).unwrap();
+    ws0.undo(&mut txr0).unwrap();
+    assert_eq!(ws0.atom(&txr0, atom0).unwrap(), None);
+    assert_eq!(ws0.atom(&txr0, atom1).unwrap(), Some((node1, 1, vec![0].into())));
+  }
+
+  #[test]
+  fn undo_after_failed_save() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!((ws.undo.len(), ws.redo.len()), (1, 0));
+
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    txr.execute_batch("DROP TABLE \".atoms.data\"").unwrap();
+    assert!(ws.barrier(&mut txr).is_err());
+    assert_eq!((ws.undo.len(), ws.redo.len()), (1, 0));
+  }
+
+  #[test]
+  fn barrier_unchanged() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, node1, atom0, edge0]: [u128; 4] = rand::thread_rng().gen();
+    ws.set_node(&txr, node0, Some(0));
+    ws.set_node(&txr, node1, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_edge(&txr, edge0, Some((node0, 2, node1)));
+    ws.barrier(&mut txr).unwrap();
+
+    // Items changed and then changed back produce no events or undo steps.
+    ws.set_node(&txr, node1, Some(1));
+    ws.set_node(&txr, node1, Some(0));
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![1].into()))).unwrap();
+    ws.set_atom(&txr, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws.set_edge(&txr, edge0, None);
+    ws.set_edge(&txr, edge0, Some((node0, 2, node1)));
+    assert!(ws.barrier(&mut txr).unwrap().is_empty());
+    assert_eq!(ws.undo(&mut txr).unwrap().len(), 4);
+    assert_eq!(ws.node(&txr, node0), None);
+  }
+
+  #[test]
+  fn cas_atom_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+    ws.set_node(&txr, node0, Some(0));
+    assert!(!ws.cas_atom(&txr, atom0, Some((node0, 1, &[])), Some((node0, 1, vec![0].into()))).unwrap());
+    assert!(ws.cas_atom(&txr, atom0, None, Some((node0, 1, vec![0].into()))).unwrap());
+    assert!(!ws.cas_atom(&txr, atom0, None, Some((node0, 1, vec![1].into()))).unwrap());
+    assert!(!ws.cas_atom(&txr, atom0, Some((node0, 2, &[0])), Some((node0, 1, vec![1].into()))).unwrap());
+    ws.barrier(&mut txr).unwrap();
+    let clock = ws.atom_clock(&txr, atom0).unwrap();
+    assert!(ws.cas_atom(&txr, atom0, Some((node0, 1, &[0])), Some((node0, 1, vec![1].into()))).unwrap());
+    assert!(ws.atom_clock(&txr, atom0).unwrap() > clock);
+    assert!(ws.cas_atom(&txr, atom0, Some((node0, 1, &[1])), None).unwrap());
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), None);
+  }
+
+  #[test]
+  fn apply_batch_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, node1, atom0, edge0]: [u128; 4] = rand::thread_rng().gen();
+    let events = ws
+      .apply_batch(
+        &mut txr,
+        [
+          Op::Node(node0, Some(0)),
+          Op::Node(node1, Some(0)),
+          Op::Atom(atom0, Some((node0, 1, vec![0].into()))),
+          Op::Atom(atom0, Some((node0, 1, vec![1].into()))),
+          Op::Edge(edge0, Some((node0, 2, node1))),
+        ],
+      )
+      .unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(ws.atom(&txr, atom0).unwrap(), Some((node0, 1, vec![1].into())));
+    assert_eq!(ws.edge(&txr, edge0), Some((node0, 2, node1)));
+
+    // Constraints still apply to the batch as a whole.
+    let events = ws.apply_batch(&mut txr, [Op::Node(node1, None)]).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(ws.edge(&txr, edge0), None);
+  }
+
+  #[test]
+  fn traverse_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, node1, node2, node3, edge0, edge1, edge2, edge3]: [u128; 8] = rand::thread_rng().gen();
+    for node in [node0, node1, node2, node3] {
+      ws.set_node(&txr, node, Some(0));
+    }
+    ws.set_edge(&txr, edge0, Some((node0, 1, node1)));
+    ws.set_edge(&txr, edge1, Some((node1, 1, node2)));
+    ws.set_edge(&txr, edge2, Some((node2, 1, node0)));
+    ws.set_edge(&txr, edge3, Some((node0, 2, node3)));
+    ws.barrier(&mut txr).unwrap();
+
+    // Cycles terminate, and other labels are not followed.
+    assert_eq!(ws.traverse(&txr, node0, 1, usize::MAX), vec![(node0, 0), (node1, 1), (node2, 2)]);
+    assert_eq!(ws.traverse(&txr, node0, 1, 1), vec![(node0, 0), (node1, 1)]);
+    assert_eq!(ws.traverse(&txr, node0, 1, 0), vec![(node0, 0)]);
+    assert_eq!(ws.traverse(&txr, node0, 2, 5), vec![(node0, 0), (node3, 1)]);
+  }
+
+  #[test]
+  fn edge_by_src_labels_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws = Workspace::new("", Constraints::new(), &mut txr).unwrap();
+    let [node0, node1, node2, edge0, edge1, edge2, edge3]: [u128; 7] = rand::thread_rng().gen();
+    let (lo, hi) = (node1.min(node2), node1.max(node2));
+    for node in [node0, node1, node2] {
+      ws.set_node(&txr, node, Some(0));
+    }
+    ws.set_edge(&txr, edge0, Some((node0, 3, hi)));
+    ws.set_edge(&txr, edge1, Some((node0, 1, lo)));
+    ws.set_edge(&txr, edge2, Some((node0, 3, lo)));
+    ws.set_edge(&txr, edge3, Some((node0, 2, lo)));
+    ws.barrier(&mut txr).unwrap();
+    assert_eq!(ws.edge_label_dst_by_src_labels(&txr, node0, &[3, 1]), vec![(1, lo), (3, lo), (3, hi)]);
+    assert_eq!(ws.edge_label_dst_by_src_labels(&txr, node0, &[]), vec![]);
+
+    // Pending modifications are included.
+    ws.set_edge(&txr, edge1, None);
+    ws.set_edge(&txr, edge3, Some((node0, 1, hi)));
+    assert_eq!(ws.edge_label_dst_by_src_labels(&txr, node0, &[3, 1]), vec![(1, hi), (3, lo), (3, hi)]);
+  }
+
+  #[test]
+  fn structure_digest_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, node1, atom0, atom1]: [u128; 4] = rand::thread_rng().gen();
+    let empty = ws0.structure_digest(&txr0, ATOMS_NAME).unwrap().unwrap();
+    assert_eq!(ws1.structure_digest(&txr1, ATOMS_NAME).unwrap(), Some(empty));
+    assert_eq!(ws0.structure_digest(&txr0, "unknown").unwrap(), None);
+
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    ws1.set_node(&txr1, node1, Some(0));
+    ws1.set_atom(&txr1, atom1, Some((node1, 1, vec![1].into()))).unwrap();
+    ws1.set_atom(&txr1, atom1, None).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_ne!(ws0.structure_digest(&txr0, NODES_NAME).unwrap(), ws1.structure_digest(&txr1, NODES_NAME).unwrap());
+
+    // Joined in opposite orders.
+    let actions0 = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    let actions1 = ws1.sync_actions(&txr1, &ws0.sync_version(&txr0)).unwrap();
+    ws0.sync_join(&txr0, &actions1).unwrap();
+    ws1.sync_join(&txr1, &actions0).unwrap();
+    for name in [NODES_NAME, ATOMS_NAME, EDGES_NAME] {
+      assert_eq!(ws0.structure_digest(&txr0, name).unwrap(), ws1.structure_digest(&txr1, name).unwrap());
+    }
+    assert_ne!(ws0.structure_digest(&txr0, ATOMS_NAME).unwrap(), Some(empty));
+
+    // Tombstones do not count.
+    ws0.barrier(&mut txr0).unwrap();
+    let digest = ws0.structure_digest(&txr0, ATOMS_NAME).unwrap();
+    ws0.remove_tombstones(&mut txr0, &[&ws1.sync_version(&txr1)]).unwrap();
+    assert_eq!(ws0.structure_digest(&txr0, ATOMS_NAME).unwrap(), digest);
+
+    // Kept in line with the data, including pending modifications.
+    let scan = |ws: &Workspace, txr: &Transactor| {
+      let nodes =
+        ws.nodes.actions(txr, BTreeMap::new()).iter().fold(0, |acc, (id, item)| acc ^ metadata::item_digest(*id, item));
+      let atoms = ws
+        .atoms
+        .actions(txr, BTreeMap::new())
+        .unwrap()
+        .iter()
+        .fold(0, |acc, (id, item)| acc ^ metadata::item_digest(*id, item));
+      [Some(nodes), Some(atoms)]
+    };
+    let digests = |ws: &Workspace, txr: &Transactor| {
+      [ws.structure_digest(txr, NODES_NAME).unwrap(), ws.structure_digest(txr, ATOMS_NAME).unwrap()]
+    };
+    let saved = digests(&ws0, &txr0);
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![2].into()))).unwrap();
+    ws0.set_node(&txr0, node1, None);
+    assert_eq!(digests(&ws0, &txr0), scan(&ws0, &txr0));
+    ws0.discard();
+    assert_eq!(digests(&ws0, &txr0), saved);
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![2].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    let saved = digests(&ws0, &txr0);
+    assert_eq!(saved, scan(&ws0, &txr0));
+
+    // Saved, or computed once for data which predates it.
+    let ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    assert_eq!(digests(&ws0, &txr0), saved);
+    txr0.execute_batch("DELETE FROM \".nodes.digest\"; DELETE FROM \".atoms.digest\";").unwrap();
+    let ws0 = Workspace::load("", Constraints::new(), &txr0).unwrap();
+    assert_eq!(digests(&ws0, &txr0), saved);
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    let get = |name: &str| {
+      txr0.query_row(&format!("SELECT digest FROM \".{name}.digest\""), (), |row| row.get::<_, [u8; 16]>(0))
+    };
+    assert_eq!([get(NODES_NAME).map(u128::from_be_bytes).ok(), get(ATOMS_NAME).map(u128::from_be_bytes).ok()], saved);
+  }
+
+  #[test]
+  fn metrics_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+    assert_eq!(ws0.metrics(), Metrics { writes: 2, events_emitted: 2, ..Default::default() });
+
+    let actions = ws0.sync_actions(&txr0, &ws1.sync_version(&txr1)).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.sync_join(&txr1, &actions).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws0.metrics().bytes_synced_out, actions.len() as u64);
+    assert_eq!(
+      ws1.metrics(),
+      Metrics {
+        actions_applied: 2,
+        actions_dropped_stale: 2,
+        bytes_synced_in: 2 * actions.len() as u64,
+        events_emitted: 2,
+        ..Default::default()
+      }
+    );
+  }
+
+  #[test]
+  fn runtime_prefix_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let [user0, user1, node0]: [u128; 3] = rand::thread_rng().gen();
+    let mut ws0 = Workspace::new(format!("user-{user0:x}"), Constraints::new(), &mut txr).unwrap();
+    let ws1 = Workspace::new(format!("user-{user1:x}"), Constraints::new(), &mut txr).unwrap();
+    ws0.set_node(&txr, node0, Some(0));
+    ws0.barrier(&mut txr).unwrap();
+    assert_eq!(ws0.node(&txr, node0), Some(0));
+    assert_eq!(ws1.node(&txr, node0), None);
+    let ws0 = Workspace::new(format!("user-{user0:x}"), Constraints::new(), &mut txr).unwrap();
+    assert_eq!(ws0.node(&txr, node0), Some(0));
+  }
+
+  #[test]
+  fn drop_workspace_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let node0: u128 = rand::thread_rng().gen();
+    let this = Workspace::new("a", Constraints::new(), &mut txr).unwrap().this();
+    for prefix in ["", "a", "a.b"] {
+      let mut ws = Workspace::new(prefix, Constraints::new(), &mut txr).unwrap();
+      ws.set_node(&txr, node0, Some(0));
+      ws.barrier(&mut txr).unwrap();
+    }
+    AtomSet::new("a", "extra", &mut txr).unwrap();
+    assert_eq!(drop_workspace(&mut txr, "a").unwrap(), 2 + 3 * 4);
+    assert_eq!(drop_workspace(&mut txr, "a").unwrap(), 0);
+
+    let tables: i64 =
+      txr.query_row("SELECT COUNT(*) FROM sqlite_schema WHERE type = 'table'", (), |row| row.get(0)).unwrap();
+    assert_eq!(tables, 2 * 11);
+    let ws = Workspace::new("a", Constraints::new(), &mut txr).unwrap();
+    assert_ne!(ws.this(), this);
+    assert_eq!(ws.node(&txr, node0), None);
+    for prefix in ["", "a.b"] {
+      assert_eq!(Workspace::new(prefix, Constraints::new(), &mut txr).unwrap().node(&txr, node0), Some(0));
+    }
+  }
+
+  #[test]
+  fn sync_legacy_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut ws0 = Workspace::new("", Constraints::new(), &mut txr0).unwrap();
+    let mut ws1 = Workspace::new("", Constraints::new(), &mut txr1).unwrap();
+    let [node0, atom0]: [u128; 2] = rand::thread_rng().gen();
+    ws0.set_node(&txr0, node0, Some(0));
+    ws0.set_atom(&txr0, atom0, Some((node0, 1, vec![0].into()))).unwrap();
+    ws0.barrier(&mut txr0).unwrap();
+
+    // Blobs as sent before framing was introduced: bare `bincode` maps.
+    let empty: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    let version = serialize(&empty).unwrap();
+    let actions = ws0.sync_actions(&txr0, &version).unwrap();
+    let legacy = unframe(&actions).unwrap().to_vec();
+    assert_eq!(legacy[0], 0);
+    ws1.sync_join(&txr1, &legacy).unwrap();
+    ws1.barrier(&mut txr1).unwrap();
+    assert_eq!(ws1.atom(&txr1, atom0).unwrap(), Some((node0, 1, vec![0].into())));
+  }
 }
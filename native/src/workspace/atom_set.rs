@@ -12,17 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rusqlite::{OptionalExtension, Result, Row};
-use std::collections::{btree_map::Entry, BTreeMap};
+use rusqlite::{params_from_iter, OptionalExtension, Result, Row, ToSql};
+use std::{
+  collections::{btree_map::Entry, BTreeMap},
+  ops::Bound,
+  sync::Arc,
+};
 
-use super::metadata::{StructureMetadata, StructureMetadataTransactor};
-use crate::Transactor;
+use super::{
+  metadata::{item_digest, Clock, StructureMetadata, StructureMetadataTransactor},
+  Outcome, MAX_PARAMS,
+};
+use crate::{StoreError, Transactor};
 
 /// A last-writer-wins element set for storing atomic data.
 #[derive(Debug)]
 pub struct AtomSet {
   metadata: StructureMetadata,
   mods: BTreeMap<u128, (Option<Item>, Item)>,
+  metas: Option<BTreeMap<u128, Box<[u8]>>>,
 }
 
 /// `(bucket, clock, (src, label, value))`.
@@ -34,32 +42,147 @@ fn item_lt(lhs: &Item, rhs: &Item) -> bool {
 
 /// Database interface for [`AtomSet`].
 pub trait AtomSetTransactor: StructureMetadataTransactor {
-  fn init(&mut self, prefix: &str, name: &str);
-  fn get(&self, prefix: &str, name: &str, id: u128) -> Option<Item>;
-  fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item);
-  fn id_label_value_by_src(&self, prefix: &str, name: &str, src: u128) -> BTreeMap<u128, (u64, Box<[u8]>)>;
-  fn id_value_by_src_label(&self, prefix: &str, name: &str, src: u128, label: u64) -> BTreeMap<u128, Box<[u8]>>;
-  fn id_src_value_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, (u128, Box<[u8]>)>;
-  fn id_src_by_label_value(&self, prefix: &str, name: &str, label: u64, value: &[u8]) -> BTreeMap<u128, u128>;
-  fn by_bucket_clock_range(&self, prefix: &str, name: &str, bucket: u64, lower: Option<u64>) -> BTreeMap<u128, Item>;
+  fn init(&mut self, prefix: &str, name: &str) -> Result<()>;
+  fn get(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Item>>;
+  fn get_clock(&self, prefix: &str, name: &str, id: u128) -> Result<Option<(u64, u64)>>;
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> Result<BTreeMap<u128, Item>>;
+  fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) -> Result<()>;
+  fn set_many(&mut self, prefix: &str, name: &str, items: Vec<(u128, Item)>) -> Result<()>;
+  fn clear(&mut self, prefix: &str, name: &str) -> Result<()>;
+  fn reindex(&mut self, prefix: &str, name: &str) -> Result<()>;
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> Result<usize>;
+  fn id_label_value_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>>;
+  fn id_value_by_src_label(&self, prefix: &str, name: &str, src: u128, label: u64)
+    -> Result<BTreeMap<u128, Box<[u8]>>>;
+  fn id_src_value_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>>;
+  fn id_src_by_label_value(&self, prefix: &str, name: &str, label: u64, value: &[u8]) -> Result<BTreeMap<u128, u128>>;
+  fn id_src_by_label_value_prefix(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>>;
+  fn count_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<u64>;
+  fn count_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<u64>;
+  fn id_label_value_by_src_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>>;
+  fn id_value_by_src_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>>;
+  fn id_src_value_by_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>>;
+  fn id_src_by_label_value_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, u128>>;
+  fn by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+  ) -> Result<BTreeMap<u128, Item>>;
+  fn by_bucket_clock_ranges(
+    &self,
+    prefix: &str,
+    name: &str,
+    ranges: &[(u64, Option<u64>)],
+  ) -> Result<BTreeMap<u128, Item>>;
+  fn for_each_by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+    f: impl FnMut(u128, Item),
+  ) -> Result<()>;
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> Result<BTreeMap<u64, u64>>;
+  fn init_meta(&mut self, prefix: &str, name: &str) -> Result<()>;
+  fn get_meta(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Box<[u8]>>>;
+  fn set_meta(&mut self, prefix: &str, name: &str, id: u128, meta: &[u8]) -> Result<()>;
 }
 
 impl AtomSet {
   /// Creates or loads data.
-  pub fn new(prefix: &'static str, name: &'static str, txr: &mut impl AtomSetTransactor) -> Self {
-    let metadata = StructureMetadata::new(prefix, name, txr);
+  pub fn new(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &mut impl AtomSetTransactor) -> Result<Self> {
+    let (prefix, name) = (prefix.into(), name.into());
+    let metadata = StructureMetadata::new(prefix.clone(), name.clone(), txr);
     let mods = BTreeMap::new();
-    txr.init(prefix, name);
-    Self { metadata, mods }
+    txr.init(&prefix, &name)?;
+    let mut res = Self { metadata, mods, metas: None };
+    res.init_digest(txr)?;
+    Ok(res)
+  }
+
+  /// Loads existing data without creating or altering any tables, e.g. on a
+  /// read-only connection. The tables must exist.
+  pub fn load(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &impl AtomSetTransactor) -> Result<Self> {
+    let metadata = StructureMetadata::load(prefix, name, txr);
+    let mut res = Self { metadata, mods: BTreeMap::new(), metas: None };
+    res.init_digest(txr)?;
+    Ok(res)
+  }
+
+  /// Computes the digest by scanning all items, if none was saved before.
+  fn init_digest(&mut self, txr: &impl AtomSetTransactor) -> Result<()> {
+    if self.metadata.digest().is_none() {
+      let actions = self.actions(txr, BTreeMap::new())?;
+      self.metadata.set_digest(actions.iter().fold(0, |acc, (id, item)| acc ^ item_digest(*id, item)));
+    }
+    Ok(())
+  }
+
+  /// Returns an order-independent digest of all current items, including
+  /// pending modifications: the XOR of [`item_digest`] over them, which is
+  /// kept up to date on every write instead of scanning.
+  pub fn digest(&self) -> u128 {
+    self.metadata.digest().unwrap_or_default()
+  }
+
+  /// Creates or loads data, with an additional opaque metadata blob for each
+  /// item (see [`Self::set_with_meta`]).
+  pub fn new_with_meta(
+    prefix: impl Into<Arc<str>>,
+    name: impl Into<Arc<str>>,
+    txr: &mut impl AtomSetTransactor,
+  ) -> Result<Self> {
+    let mut res = Self::new(prefix, name, txr)?;
+    txr.init_meta(res.prefix(), res.name())?;
+    res.metas = Some(BTreeMap::new());
+    Ok(res)
   }
 
   /// Returns the name of the workspace.
-  pub fn prefix(&self) -> &'static str {
+  pub fn prefix(&self) -> &str {
     self.metadata.prefix()
   }
 
   /// Returns the name of the structure.
-  pub fn name(&self) -> &'static str {
+  pub fn name(&self) -> &str {
     self.metadata.name()
   }
 
@@ -73,28 +196,87 @@ impl AtomSet {
     self.metadata.next()
   }
 
-  /// Returns pending modifications.
+  /// Same as [`Self::next`], using `clock` instead of the system time.
+  pub fn next_with(&self, clock: Clock) -> u64 {
+    self.metadata.next_with(clock)
+  }
+
+  /// Returns the current clock value for given bucket plus one.
+  pub fn next_for_bucket(&self, bucket: u64) -> u64 {
+    self.metadata.next_for_bucket(bucket)
+  }
+
+  /// Returns pending modifications, leaving out those which end up with the
+  /// same value as before (e.g. toggled back). These are still saved, as their
+  /// clock values may have been seen by others.
   pub fn mods(&self) -> Vec<(u128, Option<(u128, u64, Box<[u8]>)>, Option<(u128, u64, Box<[u8]>)>)> {
     let mut res = Vec::new();
     for (id, (prev, curr)) in &self.mods {
-      res.push((*id, prev.clone().and_then(|(_, _, slv)| slv), curr.clone().2));
+      let prev = prev.clone().and_then(|(_, _, slv)| slv);
+      if prev != curr.2 {
+        res.push((*id, prev, curr.clone().2));
+      }
     }
     res
   }
 
-  pub fn get(&self, txr: &impl AtomSetTransactor, id: u128) -> Option<Item> {
-    self.mods.get(&id).map_or_else(|| txr.get(self.prefix(), self.name(), id), |(_, curr)| Some(curr.clone()))
+  pub fn get(&self, txr: &impl AtomSetTransactor, id: u128) -> Result<Option<Item>> {
+    self.mods.get(&id).map_or_else(|| txr.get(self.prefix(), self.name(), id), |(_, curr)| Ok(Some(curr.clone())))
+  }
+
+  /// Returns `(bucket, clock)` of item, without reading its value.
+  pub fn clock_of(&self, txr: &impl AtomSetTransactor, id: u128) -> Result<Option<(u64, u64)>> {
+    match self.mods.get(&id) {
+      Some((_, (bucket, clock, _))) => Ok(Some((*bucket, *clock))),
+      None => txr.get_clock(self.prefix(), self.name(), id),
+    }
   }
 
-  pub fn id_label_value_by_src(&self, txr: &impl AtomSetTransactor, src: u128) -> BTreeMap<u128, (u64, Box<[u8]>)> {
-    let mut res = txr.id_label_value_by_src(self.prefix(), self.name(), src);
+  /// Returns item together with its metadata blob.
+  ///
+  /// Fails with [`StoreError::MetaNotEnabled`] if the set was not created by
+  /// [`Self::new_with_meta`].
+  pub fn get_with_meta(
+    &self,
+    txr: &impl AtomSetTransactor,
+    id: u128,
+  ) -> Result<Option<(Item, Option<Box<[u8]>>)>, StoreError> {
+    let metas = self.metas.as_ref().ok_or(StoreError::MetaNotEnabled)?;
+    match self.mods.get(&id) {
+      Some((_, curr)) => Ok(Some((curr.clone(), metas.get(&id).cloned()))),
+      None => match txr.get(self.prefix(), self.name(), id)? {
+        Some(item) => Ok(Some((item, txr.get_meta(self.prefix(), self.name(), id)?))),
+        None => Ok(None),
+      },
+    }
+  }
+
+  /// Equivalent to calling [`Self::get`] for each of `ids`, but fetches
+  /// committed data in as few queries as possible. Absent ids are omitted.
+  pub fn get_many(&self, txr: &impl AtomSetTransactor, ids: &[u128]) -> Result<BTreeMap<u128, Item>> {
+    let unmodified = ids.iter().copied().filter(|id| !self.mods.contains_key(id)).collect::<Vec<_>>();
+    let mut res = txr.get_many(self.prefix(), self.name(), &unmodified)?;
+    for id in ids {
+      if let Some((_, curr)) = self.mods.get(id) {
+        res.insert(*id, curr.clone());
+      }
+    }
+    Ok(res)
+  }
+
+  pub fn id_label_value_by_src(
+    &self,
+    txr: &impl AtomSetTransactor,
+    src: u128,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
+    let mut res = txr.id_label_value_by_src(self.prefix(), self.name(), src)?;
     for (id, (_, (_, _, slv))) in &self.mods {
       match slv {
         Some((src_, label, value)) if src_ == &src => res.insert(*id, (*label, value.clone())),
         _ => res.remove(id),
       };
     }
-    res
+    Ok(res)
   }
 
   pub fn id_value_by_src_label(
@@ -102,49 +284,190 @@ impl AtomSet {
     txr: &impl AtomSetTransactor,
     src: u128,
     label: u64,
-  ) -> BTreeMap<u128, Box<[u8]>> {
-    let mut res = txr.id_value_by_src_label(self.prefix(), self.name(), src, label);
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
+    let mut res = txr.id_value_by_src_label(self.prefix(), self.name(), src, label)?;
     for (id, (_, (_, _, slv))) in &self.mods {
       match slv {
         Some((src_, label_, value)) if src_ == &src && label_ == &label => res.insert(*id, value.clone()),
         _ => res.remove(id),
       };
     }
-    res
+    Ok(res)
   }
 
-  pub fn id_src_value_by_label(&self, txr: &impl AtomSetTransactor, label: u64) -> BTreeMap<u128, (u128, Box<[u8]>)> {
-    let mut res = txr.id_src_value_by_label(self.prefix(), self.name(), label);
+  pub fn id_src_value_by_label(
+    &self,
+    txr: &impl AtomSetTransactor,
+    label: u64,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
+    let mut res = txr.id_src_value_by_label(self.prefix(), self.name(), label)?;
     for (id, (_, (_, _, slv))) in &self.mods {
       match slv {
         Some((src, label_, value)) if label_ == &label => res.insert(*id, (*src, value.clone())),
         _ => res.remove(id),
       };
     }
-    res
+    Ok(res)
   }
 
-  pub fn id_src_by_label_value(&self, txr: &impl AtomSetTransactor, label: u64, value: &[u8]) -> BTreeMap<u128, u128> {
-    let mut res = txr.id_src_by_label_value(self.prefix(), self.name(), label, value);
+  pub fn id_src_by_label_value(
+    &self,
+    txr: &impl AtomSetTransactor,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>> {
+    let mut res = txr.id_src_by_label_value(self.prefix(), self.name(), label, value)?;
     for (id, (_, (_, _, slv))) in &self.mods {
       match slv {
         Some((src, label_, value_)) if label_ == &label && value_.as_ref() == value => res.insert(*id, *src),
         _ => res.remove(id),
       };
     }
-    res
+    Ok(res)
   }
 
-  /// Returns all actions strictly later than given clock values.
-  /// Absent entries are assumed to be `None`.
-  pub fn actions(&self, txr: &impl AtomSetTransactor, version: BTreeMap<u64, u64>) -> BTreeMap<u128, Item> {
-    let mut res = BTreeMap::new();
-    for &bucket in self.buckets().keys() {
-      let lower = version.get(&bucket).copied();
-      for (id, item) in txr.by_bucket_clock_range(self.prefix(), self.name(), bucket, lower) {
-        res.insert(id, item);
+  /// Like [`Self::id_src_by_label_value`], but matches all values starting
+  /// with `value`.
+  pub fn id_src_by_label_value_prefix(
+    &self,
+    txr: &impl AtomSetTransactor,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>> {
+    let mut res = txr.id_src_by_label_value_prefix(self.prefix(), self.name(), label, value)?;
+    for (id, (_, (_, _, slv))) in &self.mods {
+      match slv {
+        Some((src, label_, value_)) if label_ == &label && value_.starts_with(value) => res.insert(*id, *src),
+        _ => res.remove(id),
+      };
+    }
+    Ok(res)
+  }
+
+  /// Equivalent to the length of [`Self::id_label_value_by_src`].
+  pub fn count_by_src(&self, txr: &impl AtomSetTransactor, src: u128) -> Result<u64> {
+    let count = txr.count_by_src(self.prefix(), self.name(), src)?;
+    Ok(self.overlay_count(count, |(src_, _, _)| src_ == &src))
+  }
+
+  /// Equivalent to the length of [`Self::id_src_value_by_label`].
+  pub fn count_by_label(&self, txr: &impl AtomSetTransactor, label: u64) -> Result<u64> {
+    let count = txr.count_by_label(self.prefix(), self.name(), label)?;
+    Ok(self.overlay_count(count, |(_, label_, _)| label_ == &label))
+  }
+
+  /// Applies pending modifications to a count of saved matches. The saved
+  /// state of each modified item is the `prev` recorded along with it.
+  fn overlay_count(&self, mut count: u64, f: impl Fn(&(u128, u64, Box<[u8]>)) -> bool) -> u64 {
+    for (prev, (_, _, slv)) in self.mods.values() {
+      let prev = prev.as_ref().and_then(|(_, _, slv)| slv.as_ref()).is_some_and(&f);
+      let curr = slv.as_ref().is_some_and(&f);
+      match (prev, curr) {
+        (true, false) => count -= 1,
+        (false, true) => count += 1,
+        _ => (),
       }
     }
+    count
+  }
+
+  /// Paginated version of [`Self::id_label_value_by_src`]: returns the first
+  /// `limit` matches with ids greater than `after`, in order of id. Passing the
+  /// last id of a page as `after` gives the next page.
+  pub fn id_label_value_by_src_page(
+    &self,
+    txr: &impl AtomSetTransactor,
+    src: u128,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
+    let extra = self.mods_after(after).count();
+    let res = txr.id_label_value_by_src_page(self.prefix(), self.name(), src, after, limit + extra)?;
+    Ok(self.overlay_page(res, after, limit, |slv| match slv {
+      Some((src_, label, value)) if src_ == &src => Some((*label, value.clone())),
+      _ => None,
+    }))
+  }
+
+  /// See [`Self::id_label_value_by_src_page`].
+  pub fn id_value_by_src_label_page(
+    &self,
+    txr: &impl AtomSetTransactor,
+    src: u128,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
+    let extra = self.mods_after(after).count();
+    let res = txr.id_value_by_src_label_page(self.prefix(), self.name(), src, label, after, limit + extra)?;
+    Ok(self.overlay_page(res, after, limit, |slv| match slv {
+      Some((src_, label_, value)) if src_ == &src && label_ == &label => Some(value.clone()),
+      _ => None,
+    }))
+  }
+
+  /// See [`Self::id_label_value_by_src_page`].
+  pub fn id_src_value_by_label_page(
+    &self,
+    txr: &impl AtomSetTransactor,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
+    let extra = self.mods_after(after).count();
+    let res = txr.id_src_value_by_label_page(self.prefix(), self.name(), label, after, limit + extra)?;
+    Ok(self.overlay_page(res, after, limit, |slv| match slv {
+      Some((src, label_, value)) if label_ == &label => Some((*src, value.clone())),
+      _ => None,
+    }))
+  }
+
+  /// See [`Self::id_label_value_by_src_page`].
+  pub fn id_src_by_label_value_page(
+    &self,
+    txr: &impl AtomSetTransactor,
+    label: u64,
+    value: &[u8],
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, u128>> {
+    let extra = self.mods_after(after).count();
+    let res = txr.id_src_by_label_value_page(self.prefix(), self.name(), label, value, after, limit + extra)?;
+    Ok(self.overlay_page(res, after, limit, |slv| match slv {
+      Some((src, label_, value_)) if label_ == &label && value_.as_ref() == value => Some(*src),
+      _ => None,
+    }))
+  }
+
+  fn mods_after(&self, after: Option<u128>) -> impl Iterator<Item = (&u128, &(Option<Item>, Item))> {
+    self.mods.range((after.map_or(Bound::Unbounded, Bound::Excluded), Bound::Unbounded))
+  }
+
+  /// Applies pending modifications to a page of saved results. As each of them
+  /// removes at most one entry, `res` must hold up to `limit` plus the number
+  /// of pending modifications after `after` entries, for the first `limit` to
+  /// be exact.
+  fn overlay_page<T>(
+    &self,
+    mut res: BTreeMap<u128, T>,
+    after: Option<u128>,
+    limit: usize,
+    f: impl Fn(&Option<(u128, u64, Box<[u8]>)>) -> Option<T>,
+  ) -> BTreeMap<u128, T> {
+    for (id, (_, (_, _, slv))) in self.mods_after(after) {
+      match f(slv) {
+        Some(value) => res.insert(*id, value),
+        None => res.remove(id),
+      };
+    }
+    res.into_iter().take(limit).collect()
+  }
+
+  /// Returns all actions strictly later than given clock values.
+  /// Absent entries are assumed to be `None`.
+  pub fn actions(&self, txr: &impl AtomSetTransactor, version: BTreeMap<u64, u64>) -> Result<BTreeMap<u128, Item>> {
+    let ranges = self.buckets().into_keys().map(|bucket| (bucket, version.get(&bucket).copied())).collect::<Vec<_>>();
+    let mut res = txr.by_bucket_clock_ranges(self.prefix(), self.name(), &ranges)?;
     for (id, (_, item)) in &self.mods {
       let (bucket, clock, _) = item;
       if Some(clock) > version.get(bucket) {
@@ -153,7 +476,34 @@ impl AtomSet {
         res.remove(id);
       }
     }
-    res
+    Ok(res)
+  }
+
+  /// Streaming version of [`Self::actions`], calling `f` for each action
+  /// without collecting them first. Each id is visited at most once, but not
+  /// in order: saved items come first, followed by pending ones.
+  pub fn for_each_action(
+    &self,
+    txr: &impl AtomSetTransactor,
+    version: BTreeMap<u64, u64>,
+    mut f: impl FnMut(u128, Item),
+  ) -> Result<()> {
+    for &bucket in self.buckets().keys() {
+      let lower = version.get(&bucket).copied();
+      txr.for_each_by_bucket_clock_range(self.prefix(), self.name(), bucket, lower, |id, item| {
+        // Pending modifications replace saved items, so these are visited below.
+        if !self.mods.contains_key(&id) {
+          f(id, item);
+        }
+      })?;
+    }
+    for (id, (_, item)) in &self.mods {
+      let (bucket, clock, _) = item;
+      if Some(clock) > version.get(bucket) {
+        f(*id, item.clone());
+      }
+    }
+    Ok(())
   }
 
   /// Modifies item.
@@ -164,80 +514,275 @@ impl AtomSet {
     bucket: u64,
     clock: u64,
     slv: Option<(u128, u64, Box<[u8]>)>,
-  ) -> bool {
+  ) -> Result<bool> {
+    // Read first, so that nothing is changed if it fails.
+    let saved = if self.mods.contains_key(&id) { None } else { Some(txr.get(self.prefix(), self.name(), id)?) };
     if self.metadata.update(bucket, clock) {
       let item = (bucket, clock, slv);
       match self.mods.entry(id) {
         Entry::Vacant(entry) => {
-          let prev = txr.get(self.metadata.prefix(), self.metadata.name(), id);
+          let prev = saved.flatten();
           if prev.is_none() || item_lt(prev.as_ref().unwrap(), &item) {
+            self.metadata.update_digest(prev.as_ref().map_or(0, |prev| item_digest(id, prev)), item_digest(id, &item));
             entry.insert((prev, item));
-            return true;
+            if let Some(metas) = self.metas.as_mut() {
+              metas.remove(&id);
+            }
+            return Ok(true);
           }
         }
         Entry::Occupied(mut entry) => {
           if item_lt(&entry.get().1, &item) {
+            self.metadata.update_digest(item_digest(id, &entry.get().1), item_digest(id, &item));
             entry.get_mut().1 = item;
-            return true;
+            if let Some(metas) = self.metas.as_mut() {
+              metas.remove(&id);
+            }
+            return Ok(true);
+          }
+        }
+      }
+    }
+    Ok(false)
+  }
+
+  /// Modifies item, attaching an opaque metadata blob to it. The blob is not
+  /// used in comparisons or queries, and is replaced along with the item by
+  /// any later write (which clears it unless it also carries a blob).
+  ///
+  /// Fails with [`StoreError::MetaNotEnabled`] if the set was not created by
+  /// [`Self::new_with_meta`].
+  pub fn set_with_meta(
+    &mut self,
+    txr: &impl AtomSetTransactor,
+    id: u128,
+    bucket: u64,
+    clock: u64,
+    slv: Option<(u128, u64, Box<[u8]>)>,
+    meta: Option<Box<[u8]>>,
+  ) -> Result<bool, StoreError> {
+    if self.metas.is_none() {
+      return Err(StoreError::MetaNotEnabled);
+    }
+    if self.set(txr, id, bucket, clock, slv)? {
+      if let (Some(metas), Some(meta)) = (self.metas.as_mut(), meta) {
+        metas.insert(id, meta);
+      }
+      return Ok(true);
+    }
+    Ok(false)
+  }
+
+  /// Modifies items in order, reporting for each whether it was applied. The
+  /// comparison is made against the current item, including pending ones.
+  pub fn join(
+    &mut self,
+    txr: &impl AtomSetTransactor,
+    actions: impl IntoIterator<Item = (u128, Item)>,
+  ) -> Result<Vec<(u128, Outcome)>> {
+    let mut res = Vec::new();
+    for (id, item) in actions {
+      let outcome = match self.get(txr, id)? {
+        Some(curr) if (curr.0, curr.1) == (item.0, item.1) => Outcome::RejectedTie,
+        Some(curr) if !item_lt(&curr, &item) => Outcome::RejectedOlder,
+        curr => {
+          let (bucket, clock, slv) = item;
+          match (self.set(txr, id, bucket, clock, slv)?, curr) {
+            (true, None) => Outcome::Inserted,
+            (true, Some(_)) => Outcome::Applied,
+            (false, _) => Outcome::RejectedOlder,
           }
         }
+      };
+      res.push((id, outcome));
+    }
+    Ok(res)
+  }
+
+  /// Takes the pending modification of `id` out, as if it was never made apart
+  /// from its clock value, and returns the item it would have written. Put it
+  /// back with [`Self::restore`].
+  pub fn take(&mut self, id: u128) -> Option<Item> {
+    let (prev, curr) = self.mods.remove(&id)?;
+    self.metadata.update_digest(item_digest(id, &curr), prev.as_ref().map_or(0, |prev| item_digest(id, prev)));
+    Some(curr)
+  }
+
+  /// Puts back an item returned by [`Self::take`], unless a later one has been
+  /// written since. Returns whether it was put back.
+  pub fn restore(&mut self, txr: &impl AtomSetTransactor, id: u128, item: Item) -> Result<bool> {
+    let (prev, curr) = match self.mods.get(&id) {
+      Some((prev, curr)) => (prev.clone(), Some(curr.clone())),
+      None => {
+        let saved = txr.get(self.prefix(), self.name(), id)?;
+        (saved.clone(), saved)
       }
+    };
+    if curr.as_ref().is_some_and(|curr| !item_lt(curr, &item)) {
+      return Ok(false);
+    }
+    self.metadata.update_digest(curr.as_ref().map_or(0, |curr| item_digest(id, curr)), item_digest(id, &item));
+    self.mods.insert(id, (prev, item));
+    Ok(true)
+  }
+
+  /// Returns `(recorded, found)` clock values for buckets where data has
+  /// been written past the recorded clock value (see [`Self::recover`]).
+  pub fn clocks_behind(&self, txr: &impl AtomSetTransactor) -> Result<BTreeMap<u64, (Option<u64>, u64)>> {
+    let buckets = self.buckets();
+    let found = txr.max_clock_by_bucket(self.prefix(), self.name())?;
+    Ok(
+      found
+        .into_iter()
+        .map(|(bucket, clock)| (bucket, (buckets.get(&bucket).copied(), clock)))
+        .filter(|(_, (recorded, found))| *recorded < Some(*found))
+        .collect(),
+    )
+  }
+
+  /// Raises saved clock values to the largest ones present in data, in case
+  /// data was written without its metadata (e.g. from an interrupted save).
+  /// Returns whether anything was repaired.
+  pub fn recover(&mut self, txr: &mut impl AtomSetTransactor) -> Result<bool> {
+    let mut res = false;
+    for (bucket, clock) in txr.max_clock_by_bucket(self.prefix(), self.name())? {
+      res |= self.metadata.update(bucket, clock);
+    }
+    self.metadata.save(txr);
+    Ok(res)
+  }
+
+  /// Discards all pending modifications.
+  pub fn discard(&mut self) {
+    self.metadata.discard();
+    self.mods.clear();
+    if let Some(metas) = self.metas.as_mut() {
+      metas.clear();
+    }
+  }
+
+  /// Removes all items, saved or pending, and forgets all clock values. As
+  /// nothing is recorded for the removal, items will be received again on the
+  /// next synchronisation.
+  pub fn clear(&mut self, txr: &mut impl AtomSetTransactor) -> Result<()> {
+    txr.clear(self.prefix(), self.name())?;
+    self.metadata.clear(txr);
+    self.discard();
+    Ok(())
+  }
+
+  /// Drops and recreates the secondary indices from the data table, e.g. if
+  /// one went missing and queries using it fail. Can be repeated.
+  pub fn reindex(&mut self, txr: &mut impl AtomSetTransactor) -> Result<()> {
+    txr.reindex(self.prefix(), self.name())
+  }
+
+  /// See [`super::node_set::NodeSet::remove_tombstones`].
+  pub fn remove_tombstones(
+    &mut self,
+    txr: &mut impl AtomSetTransactor,
+    acknowledged: &BTreeMap<u64, u64>,
+  ) -> Result<usize> {
+    let mut res = 0;
+    for (&bucket, &upper) in acknowledged {
+      res += txr.remove_tombstones(self.prefix(), self.name(), bucket, upper)?;
     }
-    false
+    Ok(res)
   }
 
   /// Saves all pending modifications.
-  pub fn save(&mut self, txr: &mut impl AtomSetTransactor) {
+  pub fn save(&mut self, txr: &mut impl AtomSetTransactor) -> Result<()> {
+    self.save_with(txr, |_, _, _| {})
+  }
+
+  /// Saves all pending modifications, calling `f` with the id, previous and
+  /// current item of each, in order of id, once it gets written.
+  ///
+  /// On error, remaining modifications are kept pending, but the transaction
+  /// should be rolled back as it may contain part of them.
+  pub fn save_with(
+    &mut self,
+    txr: &mut impl AtomSetTransactor,
+    mut f: impl FnMut(u128, &Option<Item>, &Item),
+  ) -> Result<()> {
     self.metadata.save(txr);
-    for (id, (_, curr)) in std::mem::take(&mut self.mods) {
-      txr.set(self.prefix(), self.name(), id, curr);
+    let ids = self.mods.keys().copied().collect::<Vec<_>>();
+    for chunk in ids.chunks(MAX_PARAMS / ROW_PARAMS) {
+      let items = chunk.iter().map(|id| (*id, self.mods[id].1.clone())).collect();
+      txr.set_many(self.prefix(), self.name(), items)?;
+      for id in chunk {
+        let (prev, curr) = self.mods.remove(id).unwrap();
+        f(*id, &prev, &curr);
+      }
+    }
+    if let Some(metas) = self.metas.as_mut() {
+      for (id, meta) in std::mem::take(metas) {
+        txr.set_meta(self.metadata.prefix(), self.metadata.name(), id, &meta)?;
+      }
     }
+    Ok(())
   }
 }
 
-fn read_row(row: &Row<'_>) -> (u128, Item) {
-  let id = row.get(0).unwrap();
-  let bucket = row.get(1).unwrap();
-  let clock = row.get(2).unwrap();
-  let src: Option<_> = row.get(3).unwrap();
-  let label: Option<_> = row.get(4).unwrap();
-  let value: Option<Vec<u8>> = row.get(5).unwrap();
-  (
+fn read_row(row: &Row<'_>) -> Result<(u128, Item)> {
+  let id = row.get(0)?;
+  let bucket = row.get(1)?;
+  let clock = row.get(2)?;
+  let src: Option<_> = row.get(3)?;
+  let label: Option<_> = row.get(4)?;
+  let value: Option<Vec<u8>> = row.get(5)?;
+  Ok((
     u128::from_be_bytes(id),
     (
       u64::from_be_bytes(bucket),
       u64::from_be_bytes(clock),
       value.map(|vec| (u128::from_be_bytes(src.unwrap()), u64::from_be_bytes(label.unwrap()), vec.into())),
     ),
-  )
+  ))
 }
 
-fn read_row_id_label_value(row: &Row<'_>) -> (u128, (u64, Box<[u8]>)) {
-  let id = row.get(0).unwrap();
-  let label = row.get(1).unwrap();
-  let value: Vec<u8> = row.get(2).unwrap();
-  (u128::from_be_bytes(id), (u64::from_be_bytes(label), value.into()))
+fn read_row_id_label_value(row: &Row<'_>) -> Result<(u128, (u64, Box<[u8]>))> {
+  let id = row.get(0)?;
+  let label = row.get(1)?;
+  let value: Vec<u8> = row.get(2)?;
+  Ok((u128::from_be_bytes(id), (u64::from_be_bytes(label), value.into())))
 }
 
-fn read_row_id_value(row: &Row<'_>) -> (u128, Box<[u8]>) {
-  let id = row.get(0).unwrap();
-  let value: Vec<u8> = row.get(1).unwrap();
-  (u128::from_be_bytes(id), value.into())
+fn read_row_id_value(row: &Row<'_>) -> Result<(u128, Box<[u8]>)> {
+  let id = row.get(0)?;
+  let value: Vec<u8> = row.get(1)?;
+  Ok((u128::from_be_bytes(id), value.into()))
 }
 
-fn read_row_id_src_value(row: &Row<'_>) -> (u128, (u128, Box<[u8]>)) {
-  let id = row.get(0).unwrap();
-  let src = row.get(1).unwrap();
-  let value: Vec<u8> = row.get(2).unwrap();
-  (u128::from_be_bytes(id), (u128::from_be_bytes(src), value.into()))
+fn read_row_id_src_value(row: &Row<'_>) -> Result<(u128, (u128, Box<[u8]>))> {
+  let id = row.get(0)?;
+  let src = row.get(1)?;
+  let value: Vec<u8> = row.get(2)?;
+  Ok((u128::from_be_bytes(id), (u128::from_be_bytes(src), value.into())))
 }
 
-fn read_row_id_src(row: &Row<'_>) -> (u128, u128) {
-  let id = row.get(0).unwrap();
-  let src = row.get(1).unwrap();
-  (u128::from_be_bytes(id), u128::from_be_bytes(src))
+fn read_row_id_src(row: &Row<'_>) -> Result<(u128, u128)> {
+  let id = row.get(0)?;
+  let src = row.get(1)?;
+  Ok((u128::from_be_bytes(id), u128::from_be_bytes(src)))
 }
 
+/// Returns the smallest byte string greater than all strings starting with
+/// `prefix`, if there is one (i.e. unless `prefix` consists only of `0xff`).
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+  let mut res = prefix.to_vec();
+  while let Some(last) = res.pop() {
+    if last < 0xff {
+      res.push(last + 1);
+      return Some(res);
+    }
+  }
+  None
+}
+
+/// Number of columns written by [`make_row`].
+const ROW_PARAMS: usize = 6;
+
 fn make_row(
   id: u128,
   item: Item,
@@ -251,111 +796,794 @@ fn make_row(
 }
 
 impl AtomSetTransactor for Transactor {
-  fn init(&mut self, prefix: &str, name: &str) {
-    self
-      .execute_batch(&format!(
-        "
-        CREATE TABLE IF NOT EXISTS \"{prefix}.{name}.data\" (
-          id BLOB NOT NULL,
-          bucket BLOB NOT NULL,
-          clock BLOB NOT NULL,
-          src BLOB,
-          label BLOB,
-          value BLOB,
-          PRIMARY KEY (id)
-        ) STRICT, WITHOUT ROWID;
-
-        CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_src_label\" ON \"{prefix}.{name}.data\" (src, label);
-        CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_label_value\" ON \"{prefix}.{name}.data\" (label, value);
-        CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_bucket_clock\" ON \"{prefix}.{name}.data\" (bucket, clock);
-        "
-      ))
-      .unwrap();
-  }
-
-  fn get(&self, prefix: &str, name: &str, id: u128) -> Option<Item> {
+  fn init(&mut self, prefix: &str, name: &str) -> Result<()> {
+    self.execute_batch(&format!(
+      "
+      CREATE TABLE IF NOT EXISTS \"{prefix}.{name}.data\" (
+        id BLOB NOT NULL,
+        bucket BLOB NOT NULL,
+        clock BLOB NOT NULL,
+        src BLOB,
+        label BLOB,
+        value BLOB,
+        PRIMARY KEY (id)
+      ) STRICT, WITHOUT ROWID;
+
+      CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_src_label\" ON \"{prefix}.{name}.data\" (src, label);
+      CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_label_value\" ON \"{prefix}.{name}.data\" (label, value);
+      CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_bucket_clock\" ON \"{prefix}.{name}.data\" (bucket, clock);
+      "
+    ))
+  }
+
+  fn get(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Item>> {
     self
       .prepare_cached(&format!(
         "SELECT id, bucket, clock, src, label, value FROM \"{prefix}.{name}.data\"
         WHERE id = ?"
-      ))
-      .unwrap()
-      .query_row((id.to_be_bytes(),), |row| Ok(read_row(row)))
+      ))?
+      .query_row((id.to_be_bytes(),), read_row)
+      .optional()
+      .map(|res| res.map(|(_, item)| item))
+  }
+
+  fn get_clock(&self, prefix: &str, name: &str, id: u128) -> Result<Option<(u64, u64)>> {
+    self
+      .prepare_cached(&format!("SELECT bucket, clock FROM \"{prefix}.{name}.data\" WHERE id = ?"))?
+      .query_row((id.to_be_bytes(),), |row| {
+        let bucket = row.get(0)?;
+        let clock = row.get(1)?;
+        Ok((u64::from_be_bytes(bucket), u64::from_be_bytes(clock)))
+      })
       .optional()
-      .unwrap()
-      .map(|(_, item)| item)
   }
 
-  fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) {
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> Result<BTreeMap<u128, Item>> {
+    let mut res = BTreeMap::new();
+    for chunk in ids.chunks(MAX_PARAMS) {
+      let params = vec!["?"; chunk.len()].join(", ");
+      let mut stmt = self.prepare_cached(&format!(
+        "SELECT id, bucket, clock, src, label, value FROM \"{prefix}.{name}.data\"
+        WHERE id IN ({params})"
+      ))?;
+      for row in stmt.query_map(params_from_iter(chunk.iter().map(|id| id.to_be_bytes())), read_row)? {
+        let (id, item) = row?;
+        res.insert(id, item);
+      }
+    }
+    Ok(res)
+  }
+
+  fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) -> Result<()> {
     self
-      .prepare_cached(&format!("REPLACE INTO \"{prefix}.{name}.data\" VALUES (?, ?, ?, ?, ?, ?)"))
-      .unwrap()
-      .execute(make_row(id, item))
-      .unwrap();
+      .prepare_cached(&format!(
+        "REPLACE INTO \"{prefix}.{name}.data\" (id, bucket, clock, src, label, value) VALUES (?, ?, ?, ?, ?, ?)"
+      ))?
+      .execute(make_row(id, item))?;
+    Ok(())
+  }
+
+  fn set_many(&mut self, prefix: &str, name: &str, items: Vec<(u128, Item)>) -> Result<()> {
+    for chunk in items.chunks(MAX_PARAMS / ROW_PARAMS) {
+      let values = vec!["(?, ?, ?, ?, ?, ?)"; chunk.len()].join(", ");
+      let rows = chunk.iter().map(|(id, item)| make_row(*id, item.clone())).collect::<Vec<_>>();
+      let mut params: Vec<&dyn ToSql> = Vec::with_capacity(rows.len() * ROW_PARAMS);
+      for (id, bucket, clock, src, label, value) in &rows {
+        params.extend([id as &dyn ToSql, bucket, clock, src, label, value]);
+      }
+      self
+        .prepare_cached(&format!(
+          "REPLACE INTO \"{prefix}.{name}.data\" (id, bucket, clock, src, label, value) VALUES {values}"
+        ))?
+        .execute(params_from_iter(params))?;
+    }
+    Ok(())
   }
 
-  fn id_label_value_by_src(&self, prefix: &str, name: &str, src: u128) -> BTreeMap<u128, (u64, Box<[u8]>)> {
+  fn clear(&mut self, prefix: &str, name: &str) -> Result<()> {
+    self.execute_batch(&format!("DELETE FROM \"{prefix}.{name}.data\";"))
+  }
+
+  fn reindex(&mut self, prefix: &str, name: &str) -> Result<()> {
+    self.execute_batch(&format!(
+      "
+      DROP INDEX IF EXISTS \"{prefix}.{name}.data.idx_src_label\";
+      DROP INDEX IF EXISTS \"{prefix}.{name}.data.idx_label_value\";
+      DROP INDEX IF EXISTS \"{prefix}.{name}.data.idx_bucket_clock\";
+      "
+    ))?;
+    self.init(prefix, name)
+  }
+
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> Result<usize> {
+    self
+      .prepare_cached(&format!(
+        "DELETE FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        WHERE bucket = ? AND clock <= ? AND src IS NULL"
+      ))?
+      .execute((bucket.to_be_bytes(), upper.to_be_bytes()))
+  }
+
+  fn id_label_value_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
     self
       .prepare_cached(&format!(
         "SELECT id, label, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
         WHERE src = ?"
-      ))
-      .unwrap()
-      .query_map((src.to_be_bytes(),), |row| Ok(read_row_id_label_value(row)))
-      .unwrap()
-      .map(Result::unwrap)
+      ))?
+      .query_map((src.to_be_bytes(),), read_row_id_label_value)?
       .collect()
   }
 
-  fn id_value_by_src_label(&self, prefix: &str, name: &str, src: u128, label: u64) -> BTreeMap<u128, Box<[u8]>> {
+  fn id_value_by_src_label(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    label: u64,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
     self
       .prepare_cached(&format!(
         "SELECT id, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
         WHERE src = ? AND label = ?"
-      ))
-      .unwrap()
-      .query_map((src.to_be_bytes(), label.to_be_bytes()), |row| Ok(read_row_id_value(row)))
-      .unwrap()
-      .map(Result::unwrap)
+      ))?
+      .query_map((src.to_be_bytes(), label.to_be_bytes()), read_row_id_value)?
       .collect()
   }
 
-  fn id_src_value_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, (u128, Box<[u8]>)> {
+  fn id_src_value_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
     self
       .prepare_cached(&format!(
         "SELECT id, src, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
         WHERE label = ?"
-      ))
-      .unwrap()
-      .query_map((label.to_be_bytes(),), |row| Ok(read_row_id_src_value(row)))
-      .unwrap()
-      .map(Result::unwrap)
+      ))?
+      .query_map((label.to_be_bytes(),), read_row_id_src_value)?
       .collect()
   }
 
-  fn id_src_by_label_value(&self, prefix: &str, name: &str, label: u64, value: &[u8]) -> BTreeMap<u128, u128> {
+  fn id_src_by_label_value(&self, prefix: &str, name: &str, label: u64, value: &[u8]) -> Result<BTreeMap<u128, u128>> {
     self
       .prepare_cached(&format!(
         "SELECT id, src FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
         WHERE label = ? AND value = ?"
-      ))
-      .unwrap()
-      .query_map((label.to_be_bytes(), value), |row| Ok(read_row_id_src(row)))
-      .unwrap()
-      .map(Result::unwrap)
+      ))?
+      .query_map((label.to_be_bytes(), value), read_row_id_src)?
+      .collect()
+  }
+
+  fn id_src_by_label_value_prefix(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>> {
+    match prefix_upper_bound(value) {
+      Some(upper) => self
+        .prepare_cached(&format!(
+          "SELECT id, src FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
+          WHERE label = ? AND value >= ? AND value < ?"
+        ))?
+        .query_map((label.to_be_bytes(), value, upper), read_row_id_src)?
+        .collect(),
+      None => self
+        .prepare_cached(&format!(
+          "SELECT id, src FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
+          WHERE label = ? AND value >= ?"
+        ))?
+        .query_map((label.to_be_bytes(), value), read_row_id_src)?
+        .collect(),
+    }
+  }
+
+  fn count_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<u64> {
+    self
+      .prepare_cached(&format!(
+        "SELECT COUNT(*) FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
+        WHERE src = ?"
+      ))?
+      .query_row((src.to_be_bytes(),), |row| row.get(0))
+  }
+
+  fn count_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<u64> {
+    self
+      .prepare_cached(&format!(
+        "SELECT COUNT(*) FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
+        WHERE label = ?"
+      ))?
+      .query_row((label.to_be_bytes(),), |row| row.get(0))
+  }
+
+  fn id_label_value_by_src_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
+    self
+      .prepare_cached(&format!(
+        "SELECT id, label, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
+        WHERE src = ? AND id > ifnull(?, X'') ORDER BY id LIMIT ?"
+      ))?
+      .query_map((src.to_be_bytes(), after.map(u128::to_be_bytes), limit as i64), read_row_id_label_value)?
       .collect()
   }
 
-  fn by_bucket_clock_range(&self, prefix: &str, name: &str, bucket: u64, lower: Option<u64>) -> BTreeMap<u128, Item> {
+  fn id_value_by_src_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
+    self
+      .prepare_cached(&format!(
+        "SELECT id, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
+        WHERE src = ? AND label = ? AND id > ifnull(?, X'') ORDER BY id LIMIT ?"
+      ))?
+      .query_map(
+        (src.to_be_bytes(), label.to_be_bytes(), after.map(u128::to_be_bytes), limit as i64),
+        read_row_id_value,
+      )?
+      .collect()
+  }
+
+  fn id_src_value_by_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
+    self
+      .prepare_cached(&format!(
+        "SELECT id, src, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
+        WHERE label = ? AND id > ifnull(?, X'') ORDER BY id LIMIT ?"
+      ))?
+      .query_map((label.to_be_bytes(), after.map(u128::to_be_bytes), limit as i64), read_row_id_src_value)?
+      .collect()
+  }
+
+  fn id_src_by_label_value_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, u128>> {
+    self
+      .prepare_cached(&format!(
+        "SELECT id, src FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_label_value\"
+        WHERE label = ? AND value = ? AND id > ifnull(?, X'') ORDER BY id LIMIT ?"
+      ))?
+      .query_map((label.to_be_bytes(), value, after.map(u128::to_be_bytes), limit as i64), read_row_id_src)?
+      .collect()
+  }
+
+  fn by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+  ) -> Result<BTreeMap<u128, Item>> {
     self
       .prepare_cached(&format!(
         "SELECT id, bucket, clock, src, label, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
-        WHERE bucket = ? AND clock > ?"
-      ))
-      .unwrap()
-      .query_map((bucket.to_be_bytes(), lower.map(u64::to_be_bytes)), |row| Ok(read_row(row)))
-      .unwrap()
-      .map(Result::unwrap)
+        WHERE bucket = ? AND clock > ifnull(?, X'')"
+      ))?
+      .query_map((bucket.to_be_bytes(), lower.map(u64::to_be_bytes)), read_row)?
       .collect()
   }
+
+  /// Fetches [`Self::by_bucket_clock_range`] for many buckets with a single
+  /// `UNION ALL` statement per chunk, instead of one statement per bucket.
+  fn by_bucket_clock_ranges(
+    &self,
+    prefix: &str,
+    name: &str,
+    ranges: &[(u64, Option<u64>)],
+  ) -> Result<BTreeMap<u128, Item>> {
+    let mut res = BTreeMap::new();
+    // Each bucket takes two parameters and one compound `SELECT` term, which
+    // SQLite caps at 500 by default.
+    for chunk in ranges.chunks(MAX_PARAMS / 2) {
+      let select = format!(
+        "SELECT id, bucket, clock, src, label, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        WHERE bucket = ? AND clock > ifnull(?, X'')"
+      );
+      let sql = vec![select; chunk.len()].join(" UNION ALL ");
+      let params = chunk.iter().flat_map(|(bucket, lower)| {
+        [Some(bucket.to_be_bytes()), lower.map(u64::to_be_bytes)].map(|param| Box::new(param) as Box<dyn ToSql>)
+      });
+      for row in self.prepare_cached(&sql)?.query_map(params_from_iter(params), read_row)? {
+        let (id, item) = row?;
+        res.insert(id, item);
+      }
+    }
+    Ok(res)
+  }
+
+  fn for_each_by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+    mut f: impl FnMut(u128, Item),
+  ) -> Result<()> {
+    let mut stmt = self.prepare_cached(&format!(
+      "SELECT id, bucket, clock, src, label, value FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+      WHERE bucket = ? AND clock > ifnull(?, X'')"
+    ))?;
+    for row in stmt.query_map((bucket.to_be_bytes(), lower.map(u64::to_be_bytes)), read_row)? {
+      let (id, item) = row?;
+      f(id, item);
+    }
+    Ok(())
+  }
+
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> Result<BTreeMap<u64, u64>> {
+    self
+      .prepare_cached(&format!(
+        "SELECT bucket, MAX(clock) FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        GROUP BY bucket"
+      ))?
+      .query_map((), |row| {
+        let bucket = row.get(0)?;
+        let clock = row.get(1)?;
+        Ok((u64::from_be_bytes(bucket), u64::from_be_bytes(clock)))
+      })?
+      .collect()
+  }
+
+  fn init_meta(&mut self, prefix: &str, name: &str) -> Result<()> {
+    let exists = self
+      .prepare_cached("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = 'meta'")?
+      .query_row((format!("{prefix}.{name}.data"),), |row| row.get::<_, u64>(0))?;
+    if exists == 0 {
+      self.execute_batch(&format!("ALTER TABLE \"{prefix}.{name}.data\" ADD COLUMN meta BLOB;"))?;
+    }
+    Ok(())
+  }
+
+  fn get_meta(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Box<[u8]>>> {
+    let meta = self
+      .prepare_cached(&format!("SELECT meta FROM \"{prefix}.{name}.data\" WHERE id = ?"))?
+      .query_row((id.to_be_bytes(),), |row| row.get::<_, Option<Vec<u8>>>(0))
+      .optional()?;
+    Ok(meta.flatten().map(Into::into))
+  }
+
+  fn set_meta(&mut self, prefix: &str, name: &str, id: u128, meta: &[u8]) -> Result<()> {
+    self
+      .prepare_cached(&format!("UPDATE \"{prefix}.{name}.data\" SET meta = ? WHERE id = ?"))?
+      .execute((meta, id.to_be_bytes()))?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::Rng;
+  use rusqlite::Connection;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[test]
+  fn meta_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new_with_meta("", "atoms", &mut txr).unwrap();
+
+    let value = |v: u8| Some((0, 0, vec![v].into()));
+    assert!(set.set_with_meta(&txr, 1, 0, 1, value(1), Some(vec![233].into())).unwrap());
+    assert!(set.set_with_meta(&txr, 2, 0, 2, value(2), Some(vec![234].into())).unwrap());
+    assert_eq!(set.get_with_meta(&txr, 1).unwrap(), Some(((0, 1, value(1)), Some(vec![233].into()))));
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.get_with_meta(&txr, 1).unwrap(), Some(((0, 1, value(1)), Some(vec![233].into()))));
+    assert_eq!(set.id_src_by_label_value(&txr, 0, &[1]).unwrap(), BTreeMap::from([(1, 0)]));
+
+    // Overwritten along with the item.
+    assert!(set.set(&txr, 1, 0, 3, value(3)).unwrap());
+    assert_eq!(set.get_with_meta(&txr, 1).unwrap(), Some(((0, 3, value(3)), None)));
+    assert!(!set.set_with_meta(&txr, 2, 0, 1, value(4), Some(vec![235].into())).unwrap());
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.get_with_meta(&txr, 1).unwrap(), Some(((0, 3, value(3)), None)));
+    assert_eq!(set.get_with_meta(&txr, 2).unwrap(), Some(((0, 2, value(2)), Some(vec![234].into()))));
+    assert_eq!(set.get_with_meta(&txr, 3).unwrap(), None);
+
+    // Still usable without metadata.
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    assert!(set.set(&txr, 2, 0, 4, value(5)).unwrap());
+    set.save(&mut txr).unwrap();
+    assert!(matches!(set.get_with_meta(&txr, 2), Err(StoreError::MetaNotEnabled)));
+    assert!(matches!(set.set_with_meta(&txr, 2, 0, 5, value(6), None), Err(StoreError::MetaNotEnabled)));
+    let set = AtomSet::new_with_meta("", "atoms", &mut txr).unwrap();
+    assert_eq!(set.get_with_meta(&txr, 2).unwrap(), Some(((0, 4, value(5)), None)));
+  }
+
+  #[test]
+  fn tombstone_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    // Deletes only give way to strictly newer writes, so a concurrent write
+    // from before the delete cannot bring the item back.
+    assert!(set.set(&txr, 1, 0, 1, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(set.set(&txr, 1, 0, 5, None).unwrap());
+    set.save(&mut txr).unwrap();
+    let outcomes = set.join(&txr, [(1, (1, 3, Some((0, 0, vec![2].into()))))]).unwrap();
+    assert_eq!(outcomes, vec![(1, Outcome::RejectedOlder)]);
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.get(&txr, 1).unwrap(), Some((0, 5, None)));
+
+    let outcomes = set.join(&txr, [(1, (1, 6, Some((0, 0, vec![3].into()))))]).unwrap();
+    assert_eq!(outcomes, vec![(1, Outcome::Applied)]);
+    assert_eq!(set.get(&txr, 1).unwrap(), Some((1, 6, Some((0, 0, vec![3].into())))));
+  }
+
+  #[test]
+  fn save_with_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    assert!(set.set(&txr, 2, 0, 1, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(set.set(&txr, 1, 0, 2, None).unwrap());
+    set.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 2, 0, 3, None).unwrap());
+    assert!(set.set(&txr, 3, 0, 4, None).unwrap());
+    assert!(set.set(&txr, 1, 0, 5, None).unwrap());
+    let mut saved = Vec::new();
+    set.save_with(&mut txr, |id, prev, curr| saved.push((id, prev.clone(), curr.clone()))).unwrap();
+    assert_eq!(
+      saved,
+      vec![
+        (1, Some((0, 2, None)), (0, 5, None)),
+        (2, Some((0, 1, Some((0, 0, vec![1].into())))), (0, 3, None)),
+        (3, None, (0, 4, None)),
+      ]
+    );
+    assert!(set.mods().is_empty());
+    assert_eq!(set.get(&txr, 2).unwrap(), Some((0, 3, None)));
+  }
+
+  #[test]
+  fn error_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    assert!(set.set(&txr, 1, 0, 1, None).unwrap());
+    set.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 2, 0, 2, Some((0, 0, vec![].into()))).unwrap());
+    txr.execute_batch("DROP TABLE \".atoms.data\";").unwrap();
+
+    // Reads fail without changing anything.
+    assert!(set.get(&txr, 1).is_err());
+    assert!(set.id_src_value_by_label(&txr, 0).is_err());
+    assert!(set.set(&txr, 3, 0, 3, None).is_err());
+    assert_eq!(set.buckets(), BTreeMap::from([(0, 2)]));
+    assert_eq!(set.get(&txr, 2).unwrap(), Some((0, 2, Some((0, 0, vec![].into())))));
+
+    // Pending modifications are kept.
+    assert!(set.save(&mut txr).is_err());
+    assert_eq!(set.mods().len(), 1);
+  }
+
+  #[test]
+  fn get_many_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    // Spans several chunks, with pending, saved, removed and absent ids.
+    let n = MAX_PARAMS as u128 * 2 + 1;
+    for id in 0..n {
+      assert!(set.set(&txr, id * 2, 0, id as u64 + 1, Some((id, 0, vec![1].into()))).unwrap());
+    }
+    set.save(&mut txr).unwrap();
+    let n = n as u64;
+    for id in 0..10 {
+      assert!(set.set(&txr, id * 4, 0, n + id as u64 * 2 + 1, None).unwrap());
+      assert!(set.set(&txr, id * 4 + 1, 0, n + id as u64 * 2 + 2, Some((0, 1, vec![2].into()))).unwrap());
+    }
+    let ids = (0..n as u128 * 2).collect::<Vec<_>>();
+    let res = set.get_many(&txr, &ids).unwrap();
+    for id in ids {
+      assert_eq!(res.get(&id).cloned(), set.get(&txr, id).unwrap());
+    }
+    assert_eq!(res.len(), n as usize + 10);
+  }
+
+  #[test]
+  fn page_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    let mut rng = rand::thread_rng();
+
+    let mut clock = 0;
+    for _ in 0..3 {
+      for _ in 0..100 {
+        clock += 1;
+        let slv =
+          rng.gen_bool(0.8).then(|| (rng.gen_range(0..2), rng.gen_range(0..2), vec![rng.gen_range(0..2)].into()));
+        assert!(set.set(&txr, rng.gen_range(0..200), 0, clock, slv).unwrap());
+      }
+      set.save(&mut txr).unwrap();
+    }
+    for _ in 0..30 {
+      clock += 1;
+      let slv = rng.gen_bool(0.5).then(|| (rng.gen_range(0..2), rng.gen_range(0..2), vec![rng.gen_range(0..2)].into()));
+      assert!(set.set(&txr, rng.gen_range(0..200), 0, clock, slv).unwrap());
+    }
+
+    fn pages<T: Clone + PartialEq + std::fmt::Debug>(
+      all: BTreeMap<u128, T>,
+      mut page: impl FnMut(Option<u128>, usize) -> BTreeMap<u128, T>,
+    ) {
+      for limit in [1, 7, 1000] {
+        let mut res = BTreeMap::new();
+        let mut after = None;
+        loop {
+          let curr = page(after, limit);
+          assert!(curr.len() <= limit);
+          assert!(curr.keys().all(|id| Some(*id) > after));
+          let Some(last) = curr.keys().last().copied() else { break };
+          res.extend(curr);
+          after = Some(last);
+        }
+        assert_eq!(res, all);
+      }
+    }
+    pages(set.id_label_value_by_src(&txr, 1).unwrap(), |after, limit| {
+      set.id_label_value_by_src_page(&txr, 1, after, limit).unwrap()
+    });
+    pages(set.id_value_by_src_label(&txr, 1, 0).unwrap(), |after, limit| {
+      set.id_value_by_src_label_page(&txr, 1, 0, after, limit).unwrap()
+    });
+    pages(set.id_src_value_by_label(&txr, 0).unwrap(), |after, limit| {
+      set.id_src_value_by_label_page(&txr, 0, after, limit).unwrap()
+    });
+    pages(set.id_src_by_label_value(&txr, 1, &[1]).unwrap(), |after, limit| {
+      set.id_src_by_label_value_page(&txr, 1, &[1], after, limit).unwrap()
+    });
+  }
+
+  #[test]
+  fn prefix_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    assert_eq!(prefix_upper_bound(b"ab"), Some(b"ac".to_vec()));
+    assert_eq!(prefix_upper_bound(&[1, 0xff, 0xff]), Some(vec![2]));
+    assert_eq!(prefix_upper_bound(&[0xff, 0xff]), None);
+    assert_eq!(prefix_upper_bound(&[]), None);
+
+    let values: [&[u8]; 7] = [b"a", b"ab", b"abc", b"ac", b"b", &[0xff], &[0xff, 0xff, 0]];
+    for (i, value) in values.iter().enumerate() {
+      assert!(set.set(&txr, i as u128, 0, i as u64 + 1, Some((i as u128, 0, value.to_vec().into()))).unwrap());
+    }
+    set.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 7, 0, 8, Some((7, 0, b"abd".to_vec().into()))).unwrap());
+    assert!(set.set(&txr, 2, 0, 9, Some((2, 0, b"b".to_vec().into()))).unwrap());
+    assert!(set.set(&txr, 8, 0, 10, Some((8, 1, b"ab".to_vec().into()))).unwrap());
+
+    let ids = |value: &[u8]| set.id_src_by_label_value_prefix(&txr, 0, value).unwrap().into_keys().collect::<Vec<_>>();
+    assert_eq!(ids(b"ab"), vec![1, 7]);
+    assert_eq!(ids(b"a"), vec![0, 1, 3, 7]);
+    assert_eq!(ids(&[0xff]), vec![5, 6]);
+    assert_eq!(ids(&[0xff, 0xff]), vec![6]);
+    assert_eq!(ids(b""), (0..8).collect::<Vec<_>>());
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.id_src_by_label_value_prefix(&txr, 0, b"ab").unwrap(), BTreeMap::from([(1, 1), (7, 7)]));
+  }
+
+  #[test]
+  fn count_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    let mut rng = rand::thread_rng();
+
+    let mut clock = 0;
+    for round in 0..4 {
+      for _ in 0..50 {
+        clock += 1;
+        let slv = rng.gen_bool(0.7).then(|| (rng.gen_range(0..3), rng.gen_range(0..3), vec![].into()));
+        assert!(set.set(&txr, rng.gen_range(0..60), 0, clock, slv).unwrap());
+      }
+      for key in 0..3 {
+        assert_eq!(set.count_by_src(&txr, key).unwrap(), set.id_label_value_by_src(&txr, key).unwrap().len() as u64);
+        assert_eq!(
+          set.count_by_label(&txr, key as u64).unwrap(),
+          set.id_src_value_by_label(&txr, key as u64).unwrap().len() as u64
+        );
+      }
+      if round % 2 == 0 {
+        set.save(&mut txr).unwrap();
+      }
+    }
+  }
+
+  #[test]
+  fn mods_unchanged() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    assert!(set.set(&txr, 1, 0, 1, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(set.set(&txr, 2, 0, 2, None).unwrap());
+    set.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 1, 0, 3, Some((0, 0, vec![2].into()))).unwrap());
+    assert!(set.set(&txr, 1, 0, 4, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(set.set(&txr, 2, 0, 5, None).unwrap());
+    assert!(set.set(&txr, 3, 0, 6, None).unwrap());
+    assert_eq!(set.mods(), vec![]);
+
+    // Still saved with new clock values.
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.get(&txr, 1).unwrap(), Some((0, 4, Some((0, 0, vec![1].into())))));
+    assert_eq!(set.get(&txr, 3).unwrap(), Some((0, 6, None)));
+    assert_eq!(set.actions(&txr, BTreeMap::from([(0, 3)])).unwrap().len(), 3);
+  }
+
+  #[test]
+  fn save_many() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    // Written in chunks of `MAX_PARAMS / ROW_PARAMS` rows per statement.
+    let n: u128 = 10000;
+    for id in 0..n {
+      let slv = (id % 3 != 0).then(|| (id, id as u64 % 7, id.to_be_bytes().to_vec().into()));
+      assert!(set.set(&txr, id, 0, id as u64 + 1, slv).unwrap());
+    }
+    let expected = set.mods.iter().map(|(id, (_, curr))| (*id, curr.clone())).collect::<BTreeMap<_, _>>();
+    static STATEMENTS: AtomicUsize = AtomicUsize::new(0);
+    txr.trace(Some(|sql| {
+      if sql.starts_with("REPLACE INTO \".atoms.data\"") {
+        STATEMENTS.fetch_add(1, Ordering::Relaxed);
+      }
+    }));
+    let mut saved = Vec::new();
+    set.save_with(&mut txr, |id, prev, _| saved.push((id, prev.clone()))).unwrap();
+    txr.trace(None);
+    assert_eq!(STATEMENTS.load(Ordering::Relaxed), (n as usize).div_ceil(MAX_PARAMS / ROW_PARAMS));
+    assert_eq!(saved, (0..n).map(|id| (id, None)).collect::<Vec<_>>());
+    assert_eq!(set.actions(&txr, BTreeMap::new()).unwrap(), expected);
+  }
+
+  #[test]
+  fn for_each_action_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    let mut rng = rand::thread_rng();
+
+    let mut clocks = [0; 3];
+    for round in 0..3 {
+      for _ in 0..100 {
+        let bucket = rng.gen_range(0..3);
+        clocks[bucket] += rng.gen_range(1..3);
+        let slv = rng.gen_bool(0.5).then(|| (0, 0, vec![].into()));
+        set.set(&txr, rng.gen_range(0..100), bucket as u64, clocks[bucket], slv).unwrap();
+      }
+      if round < 2 {
+        set.save(&mut txr).unwrap();
+      }
+    }
+    for _ in 0..10 {
+      let version =
+        (0..3).map(|bucket| (bucket, rng.gen_range(0..clocks[bucket as usize] + 1))).collect::<BTreeMap<_, _>>();
+      let mut res = BTreeMap::new();
+      set.for_each_action(&txr, version.clone(), |id, item| assert!(res.insert(id, item).is_none())).unwrap();
+      assert_eq!(res, set.actions(&txr, version).unwrap());
+    }
+  }
+
+  #[test]
+  fn clear_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    let mut other = AtomSet::new("", "other", &mut txr).unwrap();
+
+    assert!(set.set(&txr, 1, 0, 1, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(other.set(&txr, 1, 0, 1, Some((0, 0, vec![1].into()))).unwrap());
+    set.save(&mut txr).unwrap();
+    other.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 2, 1, 2, None).unwrap());
+
+    set.clear(&mut txr).unwrap();
+    assert_eq!(set.buckets(), BTreeMap::new());
+    assert_eq!(set.get(&txr, 1).unwrap(), None);
+    assert_eq!(set.get(&txr, 2).unwrap(), None);
+    assert_eq!(other.get(&txr, 1).unwrap(), Some((0, 1, Some((0, 0, vec![1].into())))));
+
+    assert!(set.set(&txr, 1, 0, 1, Some((0, 0, vec![2].into()))).unwrap());
+    set.save(&mut txr).unwrap();
+    let set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    assert_eq!(set.buckets(), BTreeMap::from([(0, 1)]));
+    assert_eq!(set.get(&txr, 1).unwrap(), Some((0, 1, Some((0, 0, vec![2].into())))));
+  }
+
+  #[test]
+  fn clock_of_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+
+    assert!(set.set(&txr, 1, 2, 3, Some((0, 0, vec![1].into()))).unwrap());
+    assert!(set.set(&txr, 2, 2, 4, None).unwrap());
+    assert_eq!(set.clock_of(&txr, 1).unwrap(), Some((2, 3)));
+    set.save(&mut txr).unwrap();
+    assert!(set.set(&txr, 1, 5, 6, None).unwrap());
+    assert_eq!(set.clock_of(&txr, 1).unwrap(), Some((5, 6)));
+    assert_eq!(set.clock_of(&txr, 2).unwrap(), Some((2, 4)));
+    assert_eq!(set.clock_of(&txr, 3).unwrap(), None);
+  }
+
+  #[test]
+  fn tie_simple() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set0 = AtomSet::new("", "atoms", &mut txr0).unwrap();
+    let mut set1 = AtomSet::new("", "atoms", &mut txr1).unwrap();
+
+    // Equal clocks from different buckets are ordered by bucket, whatever the
+    // order of arrival.
+    let a = (1, (3, 5, Some((0, 0, vec![1].into()))));
+    let b = (1, (2, 5, Some((0, 0, vec![2].into()))));
+    set0.join(&txr0, [a.clone(), b.clone()]).unwrap();
+    set1.join(&txr1, [b, a.clone()]).unwrap();
+    set0.save(&mut txr0).unwrap();
+    assert_eq!(set0.get(&txr0, 1).unwrap(), Some(a.1.clone()));
+    assert_eq!(set1.get(&txr1, 1).unwrap(), Some(a.1));
+    assert_eq!(set0.actions(&txr0, BTreeMap::new()).unwrap(), set1.actions(&txr1, BTreeMap::new()).unwrap());
+  }
+
+  #[test]
+  fn actions_union_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    for (bucket, clocks) in [(0, 1..=3), (1, 10..=12), (2, 100..=102)] {
+      for clock in clocks {
+        set.set(&txr, bucket as u128 * 1000 + clock as u128, bucket, clock, Some((0, 0, vec![].into()))).unwrap();
+      }
+    }
+    set.save(&mut txr).unwrap();
+
+    let per_bucket = |version: &BTreeMap<u64, u64>| {
+      let mut res = BTreeMap::new();
+      for bucket in 0..3 {
+        res.extend(txr.by_bucket_clock_range("", "atoms", bucket, version.get(&bucket).copied()).unwrap());
+      }
+      res
+    };
+    for (version, len) in [
+      (BTreeMap::new(), 9),
+      (BTreeMap::from([(0, 2)]), 7),
+      (BTreeMap::from([(0, 3), (1, 10), (2, 101)]), 3),
+      (BTreeMap::from([(0, 0), (1, 12), (2, 102)]), 3),
+    ] {
+      let res = set.actions(&txr, version.clone()).unwrap();
+      assert_eq!(res.len(), len);
+      assert_eq!(res, per_bucket(&version));
+    }
+  }
+
+  #[test]
+  fn reindex_simple() {
+    let mut txr: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut set = AtomSet::new("", "atoms", &mut txr).unwrap();
+    set.set(&txr, 0, 0, 1, Some((1, 2, vec![3].into()))).unwrap();
+    set.save(&mut txr).unwrap();
+    assert_eq!(set.id_src_by_label_value(&txr, 2, &[3]).unwrap(), BTreeMap::from([(0, 1)]));
+
+    txr.execute_batch("DROP INDEX \".atoms.data.idx_label_value\"").unwrap();
+    assert!(set.id_src_by_label_value(&txr, 2, &[3]).is_err());
+    set.reindex(&mut txr).unwrap();
+    set.reindex(&mut txr).unwrap();
+    assert_eq!(set.id_src_by_label_value(&txr, 2, &[3]).unwrap(), BTreeMap::from([(0, 1)]));
+    assert_eq!(set.id_label_value_by_src(&txr, 1).unwrap(), BTreeMap::from([(0, (2, vec![3].into()))]));
+  }
 }
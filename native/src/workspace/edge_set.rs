@@ -12,10 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rusqlite::{OptionalExtension, Result, Row};
-use std::collections::{btree_map::Entry, BTreeMap};
-
-use super::metadata::{StructureMetadata, StructureMetadataTransactor};
+use rusqlite::{params_from_iter, OptionalExtension, Result, Row};
+use std::{
+  collections::{btree_map::Entry, BTreeMap},
+  sync::Arc,
+};
+
+use super::{
+  metadata::{item_digest, Clock, StructureMetadata, StructureMetadataTransactor},
+  Outcome, MAX_PARAMS,
+};
 use crate::Transactor;
 
 /// A last-writer-wins element set for storing edges.
@@ -36,30 +42,66 @@ fn item_lt(lhs: &Item, rhs: &Item) -> bool {
 pub trait EdgeSetTransactor: StructureMetadataTransactor {
   fn init(&mut self, prefix: &str, name: &str);
   fn get(&self, prefix: &str, name: &str, id: u128) -> Option<Item>;
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> BTreeMap<u128, Item>;
   fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item);
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> usize;
   fn id_label_dst_by_src(&self, prefix: &str, name: &str, src: u128) -> BTreeMap<u128, (u64, u128)>;
   fn id_dst_by_src_label(&self, prefix: &str, name: &str, src: u128, label: u64) -> BTreeMap<u128, u128>;
+  fn id_label_dst_by_src_labels(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    labels: &[u64],
+  ) -> BTreeMap<u128, (u64, u128)>;
   fn id_src_label_by_dst(&self, prefix: &str, name: &str, dst: u128) -> BTreeMap<u128, (u128, u64)>;
   fn id_src_by_dst_label(&self, prefix: &str, name: &str, dst: u128, label: u64) -> BTreeMap<u128, u128>;
+  fn id_src_dst_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, (u128, u128)>;
   fn by_bucket_clock_range(&self, prefix: &str, name: &str, bucket: u64, lower: Option<u64>) -> Vec<(u128, Item)>;
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64>;
 }
 
 impl EdgeSet {
   /// Creates or loads data.
-  pub fn new(prefix: &'static str, name: &'static str, txr: &mut impl EdgeSetTransactor) -> Self {
-    let metadata = StructureMetadata::new(prefix, name, txr);
+  pub fn new(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &mut impl EdgeSetTransactor) -> Self {
+    let (prefix, name) = (prefix.into(), name.into());
+    let metadata = StructureMetadata::new(prefix.clone(), name.clone(), txr);
     let mods = BTreeMap::new();
-    txr.init(prefix, name);
-    Self { metadata, mods }
+    txr.init(&prefix, &name);
+    let mut res = Self { metadata, mods };
+    res.init_digest(txr);
+    res
+  }
+
+  /// Loads existing data without creating or altering any tables, e.g. on a
+  /// read-only connection. The tables must exist.
+  pub fn load(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &impl EdgeSetTransactor) -> Self {
+    let metadata = StructureMetadata::load(prefix, name, txr);
+    let mut res = Self { metadata, mods: BTreeMap::new() };
+    res.init_digest(txr);
+    res
+  }
+
+  /// Computes the digest by scanning all items, if none was saved before.
+  fn init_digest(&mut self, txr: &impl EdgeSetTransactor) {
+    if self.metadata.digest().is_none() {
+      let actions = self.actions(txr, BTreeMap::new());
+      self.metadata.set_digest(actions.iter().fold(0, |acc, (id, item)| acc ^ item_digest(*id, item)));
+    }
+  }
+
+  /// See [`super::atom_set::AtomSet::digest`].
+  pub fn digest(&self) -> u128 {
+    self.metadata.digest().unwrap_or_default()
   }
 
   /// Returns the name of the workspace.
-  pub fn prefix(&self) -> &'static str {
+  pub fn prefix(&self) -> &str {
     self.metadata.prefix()
   }
 
   /// Returns the name of the structure.
-  pub fn name(&self) -> &'static str {
+  pub fn name(&self) -> &str {
     self.metadata.name()
   }
 
@@ -73,11 +115,25 @@ impl EdgeSet {
     self.metadata.next()
   }
 
-  /// Returns pending modifications.
+  /// Same as [`Self::next`], using `clock` instead of the system time.
+  pub fn next_with(&self, clock: Clock) -> u64 {
+    self.metadata.next_with(clock)
+  }
+
+  /// Returns the current clock value for given bucket plus one.
+  pub fn next_for_bucket(&self, bucket: u64) -> u64 {
+    self.metadata.next_for_bucket(bucket)
+  }
+
+  /// Returns pending modifications, leaving out those which end up with the
+  /// same value as before (see [`super::atom_set::AtomSet::mods`]).
   pub fn mods(&self) -> Vec<(u128, Option<(u128, u64, u128)>, Option<(u128, u64, u128)>)> {
     let mut res = Vec::new();
     for (id, (prev, curr)) in &self.mods {
-      res.push((*id, prev.and_then(|(_, _, sld)| sld), curr.2));
+      let prev = prev.and_then(|(_, _, sld)| sld);
+      if prev != curr.2 {
+        res.push((*id, prev, curr.2));
+      }
     }
     res
   }
@@ -86,6 +142,19 @@ impl EdgeSet {
     self.mods.get(&id).map_or_else(|| txr.get(self.prefix(), self.name(), id), |(_, curr)| Some(*curr))
   }
 
+  /// Equivalent to calling [`Self::get`] for each of `ids`, but fetches
+  /// committed data in as few queries as possible. Absent ids are omitted.
+  pub fn get_many(&self, txr: &impl EdgeSetTransactor, ids: &[u128]) -> BTreeMap<u128, Item> {
+    let unmodified = ids.iter().copied().filter(|id| !self.mods.contains_key(id)).collect::<Vec<_>>();
+    let mut res = txr.get_many(self.prefix(), self.name(), &unmodified);
+    for id in ids {
+      if let Some((_, curr)) = self.mods.get(id) {
+        res.insert(*id, *curr);
+      }
+    }
+    res
+  }
+
   pub fn id_label_dst_by_src(&self, txr: &impl EdgeSetTransactor, src: u128) -> BTreeMap<u128, (u64, u128)> {
     let mut res = txr.id_label_dst_by_src(self.prefix(), self.name(), src);
     for (id, (_, (_, _, sld))) in &self.mods {
@@ -108,6 +177,24 @@ impl EdgeSet {
     res
   }
 
+  /// Same as [`Self::id_label_dst_by_src`], restricted to edges whose label
+  /// is one of `labels`.
+  pub fn id_label_dst_by_src_labels(
+    &self,
+    txr: &impl EdgeSetTransactor,
+    src: u128,
+    labels: &[u64],
+  ) -> BTreeMap<u128, (u64, u128)> {
+    let mut res = txr.id_label_dst_by_src_labels(self.prefix(), self.name(), src, labels);
+    for (id, (_, (_, _, sld))) in &self.mods {
+      match sld {
+        Some((src_, label, dst)) if src_ == &src && labels.contains(label) => res.insert(*id, (*label, *dst)),
+        _ => res.remove(id),
+      };
+    }
+    res
+  }
+
   pub fn id_src_label_by_dst(&self, txr: &impl EdgeSetTransactor, dst: u128) -> BTreeMap<u128, (u128, u64)> {
     let mut res = txr.id_src_label_by_dst(self.prefix(), self.name(), dst);
     for (id, (_, (_, _, sld))) in &self.mods {
@@ -130,6 +217,21 @@ impl EdgeSet {
     res
   }
 
+  /// Returns all edges with given label, regardless of endpoints. This scans
+  /// the label index and materialises every match, so the cost is linear in
+  /// the number of such edges; prefer the `src` or `dst` scoped queries when
+  /// one endpoint is known.
+  pub fn id_src_dst_by_label(&self, txr: &impl EdgeSetTransactor, label: u64) -> BTreeMap<u128, (u128, u128)> {
+    let mut res = txr.id_src_dst_by_label(self.prefix(), self.name(), label);
+    for (id, (_, (_, _, sld))) in &self.mods {
+      match sld {
+        Some((src, label_, dst)) if label_ == &label => res.insert(*id, (*src, *dst)),
+        _ => res.remove(id),
+      };
+    }
+    res
+  }
+
   /// Returns all actions strictly later than given clock values.
   /// Absent entries are assumed to be `None`.
   pub fn actions(&self, txr: &impl EdgeSetTransactor, version: BTreeMap<u64, u64>) -> BTreeMap<u128, Item> {
@@ -166,12 +268,14 @@ impl EdgeSet {
         Entry::Vacant(entry) => {
           let prev = txr.get(self.metadata.prefix(), self.metadata.name(), id);
           if prev.is_none() || item_lt(prev.as_ref().unwrap(), &item) {
+            self.metadata.update_digest(prev.as_ref().map_or(0, |prev| item_digest(id, prev)), item_digest(id, &item));
             entry.insert((prev, item));
             return true;
           }
         }
         Entry::Occupied(mut entry) => {
           if item_lt(&entry.get().1, &item) {
+            self.metadata.update_digest(item_digest(id, &entry.get().1), item_digest(id, &item));
             entry.get_mut().1 = item;
             return true;
           }
@@ -181,6 +285,67 @@ impl EdgeSet {
     false
   }
 
+  /// Modifies items in order, reporting for each whether it was applied. The
+  /// comparison is made against the current item, including pending ones.
+  pub fn join(
+    &mut self,
+    txr: &impl EdgeSetTransactor,
+    actions: impl IntoIterator<Item = (u128, Item)>,
+  ) -> Vec<(u128, Outcome)> {
+    let mut res = Vec::new();
+    for (id, item) in actions {
+      let outcome = match self.get(txr, id) {
+        Some(curr) if (curr.0, curr.1) == (item.0, item.1) => Outcome::RejectedTie,
+        Some(curr) if !item_lt(&curr, &item) => Outcome::RejectedOlder,
+        curr => {
+          let (bucket, clock, sld) = item;
+          match (self.set(txr, id, bucket, clock, sld), curr) {
+            (true, None) => Outcome::Inserted,
+            (true, Some(_)) => Outcome::Applied,
+            (false, _) => Outcome::RejectedOlder,
+          }
+        }
+      };
+      res.push((id, outcome));
+    }
+    res
+  }
+
+  /// Returns `(recorded, found)` clock values for buckets where data has
+  /// been written past the recorded clock value (see [`Self::recover`]).
+  pub fn clocks_behind(&self, txr: &impl EdgeSetTransactor) -> BTreeMap<u64, (Option<u64>, u64)> {
+    let buckets = self.buckets();
+    let found = txr.max_clock_by_bucket(self.prefix(), self.name());
+    found
+      .into_iter()
+      .map(|(bucket, clock)| (bucket, (buckets.get(&bucket).copied(), clock)))
+      .filter(|(_, (recorded, found))| *recorded < Some(*found))
+      .collect()
+  }
+
+  /// Raises saved clock values to the largest ones present in data, in case
+  /// data was written without its metadata (e.g. from an interrupted save).
+  /// Returns whether anything was repaired.
+  pub fn recover(&mut self, txr: &mut impl EdgeSetTransactor) -> bool {
+    let mut res = false;
+    for (bucket, clock) in txr.max_clock_by_bucket(self.prefix(), self.name()) {
+      res |= self.metadata.update(bucket, clock);
+    }
+    self.metadata.save(txr);
+    res
+  }
+
+  /// Discards all pending modifications.
+  pub fn discard(&mut self) {
+    self.metadata.discard();
+    self.mods.clear();
+  }
+
+  /// See [`super::node_set::NodeSet::remove_tombstones`].
+  pub fn remove_tombstones(&mut self, txr: &mut impl EdgeSetTransactor, acknowledged: &BTreeMap<u64, u64>) -> usize {
+    acknowledged.iter().map(|(&bucket, &upper)| txr.remove_tombstones(self.prefix(), self.name(), bucket, upper)).sum()
+  }
+
   /// Saves all pending modifications.
   pub fn save(&mut self, txr: &mut impl EdgeSetTransactor) {
     self.metadata.save(txr);
@@ -233,6 +398,13 @@ fn read_row_id_src(row: &Row<'_>) -> (u128, u128) {
   (u128::from_be_bytes(id), u128::from_be_bytes(src))
 }
 
+fn read_row_id_src_dst(row: &Row<'_>) -> (u128, (u128, u128)) {
+  let id = row.get(0).unwrap();
+  let src = row.get(1).unwrap();
+  let dst = row.get(2).unwrap();
+  (u128::from_be_bytes(id), (u128::from_be_bytes(src), u128::from_be_bytes(dst)))
+}
+
 fn make_row(id: u128, item: Item) -> ([u8; 16], [u8; 8], [u8; 8], Option<[u8; 16]>, Option<[u8; 8]>, Option<[u8; 16]>) {
   let (bucket, clock, sld) = item;
   let (src, label, dst) = match sld {
@@ -259,6 +431,7 @@ impl EdgeSetTransactor for Transactor {
 
         CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_src_label\" ON \"{prefix}.{name}.data\" (src, label);
         CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_dst_label\" ON \"{prefix}.{name}.data\" (dst, label);
+        CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_label\" ON \"{prefix}.{name}.data\" (label);
         CREATE INDEX IF NOT EXISTS \"{prefix}.{name}.data.idx_bucket_clock\" ON \"{prefix}.{name}.data\" (bucket, clock);
         "
       ))
@@ -278,6 +451,22 @@ impl EdgeSetTransactor for Transactor {
       .map(|(_, item)| item)
   }
 
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> BTreeMap<u128, Item> {
+    let mut res = BTreeMap::new();
+    for chunk in ids.chunks(MAX_PARAMS) {
+      let params = vec!["?"; chunk.len()].join(", ");
+      let mut stmt = self
+        .prepare_cached(&format!(
+          "SELECT id, bucket, clock, src, label, dst FROM \"{prefix}.{name}.data\"
+          WHERE id IN ({params})"
+        ))
+        .unwrap();
+      let rows = stmt.query_map(params_from_iter(chunk.iter().map(|id| id.to_be_bytes())), |row| Ok(read_row(row)));
+      res.extend(rows.unwrap().map(Result::unwrap));
+    }
+    res
+  }
+
   fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) {
     self
       .prepare_cached(&format!("REPLACE INTO \"{prefix}.{name}.data\" VALUES (?, ?, ?, ?, ?, ?)"))
@@ -286,6 +475,17 @@ impl EdgeSetTransactor for Transactor {
       .unwrap();
   }
 
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> usize {
+    self
+      .prepare_cached(&format!(
+        "DELETE FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        WHERE bucket = ? AND clock <= ? AND dst IS NULL"
+      ))
+      .unwrap()
+      .execute((bucket.to_be_bytes(), upper.to_be_bytes()))
+      .unwrap()
+  }
+
   fn id_label_dst_by_src(&self, prefix: &str, name: &str, src: u128) -> BTreeMap<u128, (u64, u128)> {
     self
       .prepare_cached(&format!(
@@ -312,6 +512,30 @@ impl EdgeSetTransactor for Transactor {
       .collect()
   }
 
+  fn id_label_dst_by_src_labels(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    labels: &[u64],
+  ) -> BTreeMap<u128, (u64, u128)> {
+    let mut res = BTreeMap::new();
+    for chunk in labels.chunks(MAX_PARAMS - 1) {
+      let params = vec!["?"; chunk.len()].join(", ");
+      let mut stmt = self
+        .prepare_cached(&format!(
+          "SELECT id, label, dst FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_src_label\"
+          WHERE src = ? AND label IN ({params})"
+        ))
+        .unwrap();
+      let values =
+        std::iter::once(src.to_be_bytes().to_vec()).chain(chunk.iter().map(|label| label.to_be_bytes().to_vec()));
+      let rows = stmt.query_map(params_from_iter(values), |row| Ok(read_row_id_label_dst(row)));
+      res.extend(rows.unwrap().map(Result::unwrap));
+    }
+    res
+  }
+
   fn id_src_label_by_dst(&self, prefix: &str, name: &str, dst: u128) -> BTreeMap<u128, (u128, u64)> {
     self
       .prepare_cached(&format!(
@@ -338,11 +562,25 @@ impl EdgeSetTransactor for Transactor {
       .collect()
   }
 
+  fn id_src_dst_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, (u128, u128)> {
+    // Not `INDEXED BY`, as databases opened read-only may predate the index.
+    self
+      .prepare_cached(&format!(
+        "SELECT id, src, dst FROM \"{prefix}.{name}.data\"
+        WHERE label = ?"
+      ))
+      .unwrap()
+      .query_map((label.to_be_bytes(),), |row| Ok(read_row_id_src_dst(row)))
+      .unwrap()
+      .map(Result::unwrap)
+      .collect()
+  }
+
   fn by_bucket_clock_range(&self, prefix: &str, name: &str, bucket: u64, lower: Option<u64>) -> Vec<(u128, Item)> {
     self
       .prepare_cached(&format!(
         "SELECT id, bucket, clock, src, label, dst FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
-        WHERE bucket = ? AND clock > ? ORDER BY clock ASC"
+        WHERE bucket = ? AND clock > ifnull(?, X'') ORDER BY clock ASC"
       ))
       .unwrap()
       .query_map((bucket.to_be_bytes(), lower.map(u64::to_be_bytes)), |row| Ok(read_row(row)))
@@ -350,4 +588,21 @@ impl EdgeSetTransactor for Transactor {
       .map(Result::unwrap)
       .collect()
   }
+
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64> {
+    self
+      .prepare_cached(&format!(
+        "SELECT bucket, MAX(clock) FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        GROUP BY bucket"
+      ))
+      .unwrap()
+      .query_map((), |row| {
+        let bucket = row.get(0).unwrap();
+        let clock = row.get(1).unwrap();
+        Ok((u64::from_be_bytes(bucket), u64::from_be_bytes(clock)))
+      })
+      .unwrap()
+      .map(Result::unwrap)
+      .collect()
+  }
 }
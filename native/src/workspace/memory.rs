@@ -0,0 +1,362 @@
+// Copyright 2024 ParkourLabs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A reference in-memory backend, for testing structures without SQLite.
+
+use rusqlite::Result;
+use std::collections::BTreeMap;
+
+use super::{atom_set::AtomSetTransactor, metadata::StructureMetadataTransactor};
+
+/// `(bucket, clock, (src, label, value))`.
+type Item = (u64, u64, Option<(u128, u64, Box<[u8]>)>);
+
+/// Keeps everything in `BTreeMap`s, returning the same results as
+/// [`crate::Transactor`] does. Queries scan all items.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryTransactor {
+  buckets: BTreeMap<String, BTreeMap<u64, u64>>,
+  digests: BTreeMap<String, u128>,
+  atoms: BTreeMap<String, BTreeMap<u128, (Item, Option<Box<[u8]>>)>>,
+}
+
+impl MemoryTransactor {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn atoms(&self, prefix: &str, name: &str) -> impl Iterator<Item = (u128, &Item)> {
+    self.atoms.get(&format!("{prefix}.{name}")).into_iter().flatten().map(|(id, (item, _))| (*id, item))
+  }
+
+  fn atoms_mut(&mut self, prefix: &str, name: &str) -> &mut BTreeMap<u128, (Item, Option<Box<[u8]>>)> {
+    self.atoms.entry(format!("{prefix}.{name}")).or_default()
+  }
+
+  fn filter<'a, T>(
+    &'a self,
+    prefix: &str,
+    name: &str,
+    f: impl Fn(&(u128, u64, Box<[u8]>)) -> Option<T> + 'a,
+  ) -> impl Iterator<Item = (u128, T)> + 'a {
+    self.atoms(prefix, name).filter_map(move |(id, (_, _, slv))| slv.as_ref().and_then(&f).map(|value| (id, value)))
+  }
+
+  fn page<T>(iter: impl Iterator<Item = (u128, T)>, after: Option<u128>, limit: usize) -> BTreeMap<u128, T> {
+    iter.filter(|(id, _)| Some(*id) > after).take(limit).collect()
+  }
+}
+
+impl StructureMetadataTransactor for MemoryTransactor {
+  fn init_buckets(&mut self, prefix: &str, name: &str) {
+    self.buckets.entry(format!("{prefix}.{name}")).or_default();
+  }
+
+  fn get_buckets(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64> {
+    self.buckets.get(&format!("{prefix}.{name}")).cloned().unwrap_or_default()
+  }
+
+  fn set_bucket(&mut self, prefix: &str, name: &str, bucket: u64, clock: u64) {
+    self.buckets.entry(format!("{prefix}.{name}")).or_default().insert(bucket, clock);
+  }
+
+  fn clear_buckets(&mut self, prefix: &str, name: &str) {
+    self.buckets.remove(&format!("{prefix}.{name}"));
+  }
+
+  fn init_digest(&mut self, _: &str, _: &str) {}
+
+  fn get_digest(&self, prefix: &str, name: &str) -> Option<u128> {
+    self.digests.get(&format!("{prefix}.{name}")).copied()
+  }
+
+  fn put_digest(&mut self, prefix: &str, name: &str, digest: u128) {
+    self.digests.insert(format!("{prefix}.{name}"), digest);
+  }
+}
+
+impl AtomSetTransactor for MemoryTransactor {
+  fn init(&mut self, prefix: &str, name: &str) -> Result<()> {
+    self.atoms_mut(prefix, name);
+    Ok(())
+  }
+
+  fn get(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Item>> {
+    Ok(self.atoms.get(&format!("{prefix}.{name}")).and_then(|atoms| atoms.get(&id)).map(|(item, _)| item.clone()))
+  }
+
+  fn get_clock(&self, prefix: &str, name: &str, id: u128) -> Result<Option<(u64, u64)>> {
+    Ok(self.get(prefix, name, id)?.map(|(bucket, clock, _)| (bucket, clock)))
+  }
+
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> Result<BTreeMap<u128, Item>> {
+    Ok(self.atoms(prefix, name).filter(|(id, _)| ids.contains(id)).map(|(id, item)| (id, item.clone())).collect())
+  }
+
+  fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) -> Result<()> {
+    self.atoms_mut(prefix, name).insert(id, (item, None));
+    Ok(())
+  }
+
+  fn set_many(&mut self, prefix: &str, name: &str, items: Vec<(u128, Item)>) -> Result<()> {
+    for (id, item) in items {
+      self.set(prefix, name, id, item)?;
+    }
+    Ok(())
+  }
+
+  fn clear(&mut self, prefix: &str, name: &str) -> Result<()> {
+    self.atoms_mut(prefix, name).clear();
+    Ok(())
+  }
+
+  fn reindex(&mut self, _: &str, _: &str) -> Result<()> {
+    Ok(())
+  }
+
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> Result<usize> {
+    let atoms = self.atoms_mut(prefix, name);
+    let len = atoms.len();
+    atoms.retain(|_, ((bucket_, clock, slv), _)| !(*bucket_ == bucket && *clock <= upper && slv.is_none()));
+    Ok(len - atoms.len())
+  }
+
+  fn id_label_value_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
+    Ok(self.filter(prefix, name, |(src_, label, value)| (*src_ == src).then(|| (*label, value.clone()))).collect())
+  }
+
+  fn id_value_by_src_label(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    label: u64,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
+    Ok(
+      self
+        .filter(prefix, name, |(src_, label_, value)| (*src_ == src && *label_ == label).then(|| value.clone()))
+        .collect(),
+    )
+  }
+
+  fn id_src_value_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
+    Ok(self.filter(prefix, name, |(src, label_, value)| (*label_ == label).then(|| (*src, value.clone()))).collect())
+  }
+
+  fn id_src_by_label_value(&self, prefix: &str, name: &str, label: u64, value: &[u8]) -> Result<BTreeMap<u128, u128>> {
+    Ok(
+      self
+        .filter(prefix, name, |(src, label_, value_)| (*label_ == label && value_.as_ref() == value).then_some(*src))
+        .collect(),
+    )
+  }
+
+  fn id_src_by_label_value_prefix(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+  ) -> Result<BTreeMap<u128, u128>> {
+    Ok(
+      self
+        .filter(prefix, name, |(src, label_, value_)| (*label_ == label && value_.starts_with(value)).then_some(*src))
+        .collect(),
+    )
+  }
+
+  fn count_by_src(&self, prefix: &str, name: &str, src: u128) -> Result<u64> {
+    Ok(self.filter(prefix, name, |(src_, _, _)| (*src_ == src).then_some(())).count() as u64)
+  }
+
+  fn count_by_label(&self, prefix: &str, name: &str, label: u64) -> Result<u64> {
+    Ok(self.filter(prefix, name, |(_, label_, _)| (*label_ == label).then_some(())).count() as u64)
+  }
+
+  fn id_label_value_by_src_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u64, Box<[u8]>)>> {
+    let iter = self.filter(prefix, name, |(src_, label, value)| (*src_ == src).then(|| (*label, value.clone())));
+    Ok(Self::page(iter, after, limit))
+  }
+
+  fn id_value_by_src_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    src: u128,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, Box<[u8]>>> {
+    let iter =
+      self.filter(prefix, name, |(src_, label_, value)| (*src_ == src && *label_ == label).then(|| value.clone()));
+    Ok(Self::page(iter, after, limit))
+  }
+
+  fn id_src_value_by_label_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, (u128, Box<[u8]>)>> {
+    let iter = self.filter(prefix, name, |(src, label_, value)| (*label_ == label).then(|| (*src, value.clone())));
+    Ok(Self::page(iter, after, limit))
+  }
+
+  fn id_src_by_label_value_page(
+    &self,
+    prefix: &str,
+    name: &str,
+    label: u64,
+    value: &[u8],
+    after: Option<u128>,
+    limit: usize,
+  ) -> Result<BTreeMap<u128, u128>> {
+    let iter =
+      self.filter(prefix, name, |(src, label_, value_)| (*label_ == label && value_.as_ref() == value).then_some(*src));
+    Ok(Self::page(iter, after, limit))
+  }
+
+  fn by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+  ) -> Result<BTreeMap<u128, Item>> {
+    let mut res = BTreeMap::new();
+    self.for_each_by_bucket_clock_range(prefix, name, bucket, lower, |id, item| {
+      res.insert(id, item);
+    })?;
+    Ok(res)
+  }
+
+  fn by_bucket_clock_ranges(
+    &self,
+    prefix: &str,
+    name: &str,
+    ranges: &[(u64, Option<u64>)],
+  ) -> Result<BTreeMap<u128, Item>> {
+    let mut res = BTreeMap::new();
+    for &(bucket, lower) in ranges {
+      res.extend(self.by_bucket_clock_range(prefix, name, bucket, lower)?);
+    }
+    Ok(res)
+  }
+
+  fn for_each_by_bucket_clock_range(
+    &self,
+    prefix: &str,
+    name: &str,
+    bucket: u64,
+    lower: Option<u64>,
+    mut f: impl FnMut(u128, Item),
+  ) -> Result<()> {
+    for (id, item) in self.atoms(prefix, name) {
+      if item.0 == bucket && Some(item.1) > lower {
+        f(id, item.clone());
+      }
+    }
+    Ok(())
+  }
+
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> Result<BTreeMap<u64, u64>> {
+    let mut res = BTreeMap::new();
+    for (_, (bucket, clock, _)) in self.atoms(prefix, name) {
+      let entry = res.entry(*bucket).or_insert(*clock);
+      *entry = (*entry).max(*clock);
+    }
+    Ok(res)
+  }
+
+  fn init_meta(&mut self, _: &str, _: &str) -> Result<()> {
+    Ok(())
+  }
+
+  fn get_meta(&self, prefix: &str, name: &str, id: u128) -> Result<Option<Box<[u8]>>> {
+    Ok(self.atoms.get(&format!("{prefix}.{name}")).and_then(|atoms| atoms.get(&id)).and_then(|(_, meta)| meta.clone()))
+  }
+
+  fn set_meta(&mut self, prefix: &str, name: &str, id: u128, meta: &[u8]) -> Result<()> {
+    if let Some((_, meta_)) = self.atoms_mut(prefix, name).get_mut(&id) {
+      *meta_ = Some(meta.into());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{workspace::atom_set::AtomSet, Transactor};
+  use rand::Rng;
+  use rusqlite::Connection;
+
+  #[test]
+  fn parity_random() {
+    let mut txr0: Transactor = Connection::open_in_memory().unwrap().try_into().unwrap();
+    let mut txr1 = MemoryTransactor::new();
+    let mut set0 = AtomSet::new("", "atoms", &mut txr0).unwrap();
+    let mut set1 = AtomSet::new("", "atoms", &mut txr1).unwrap();
+    let mut rng = rand::thread_rng();
+
+    let mut clocks = [0; 3];
+    for round in 0..10 {
+      for _ in 0..50 {
+        let bucket = rng.gen_range(0..3);
+        clocks[bucket] += rng.gen_range(1..3);
+        let slv =
+          rng.gen_bool(0.7).then(|| (rng.gen_range(0..3), rng.gen_range(0..3), vec![rng.gen_range(0..3)].into()));
+        let id = rng.gen_range(0..50);
+        let res0 = set0.set(&txr0, id, bucket as u64, clocks[bucket], slv.clone()).unwrap();
+        let res1 = set1.set(&txr1, id, bucket as u64, clocks[bucket], slv).unwrap();
+        assert_eq!(res0, res1);
+      }
+      if round % 3 != 0 {
+        set0.save(&mut txr0).unwrap();
+        set1.save(&mut txr1).unwrap();
+      }
+
+      let version =
+        (0..3).map(|bucket| (bucket, rng.gen_range(0..clocks[bucket as usize] + 1))).collect::<BTreeMap<_, _>>();
+      assert_eq!(set0.actions(&txr0, version.clone()).unwrap(), set1.actions(&txr1, version).unwrap());
+      assert_eq!(set0.buckets(), set1.buckets());
+      for key in 0..3 {
+        let (src, label, value) = (key as u128, key as u64, [key as u8]);
+        assert_eq!(set0.id_label_value_by_src(&txr0, src).unwrap(), set1.id_label_value_by_src(&txr1, src).unwrap());
+        assert_eq!(
+          set0.id_src_value_by_label(&txr0, label).unwrap(),
+          set1.id_src_value_by_label(&txr1, label).unwrap()
+        );
+        assert_eq!(
+          set0.id_src_by_label_value(&txr0, label, &value).unwrap(),
+          set1.id_src_by_label_value(&txr1, label, &value).unwrap()
+        );
+        assert_eq!(set0.count_by_label(&txr0, label).unwrap(), set1.count_by_label(&txr1, label).unwrap());
+        assert_eq!(
+          set0.id_src_value_by_label_page(&txr0, label, Some(10), 5).unwrap(),
+          set1.id_src_value_by_label_page(&txr1, label, Some(10), 5).unwrap()
+        );
+      }
+    }
+  }
+}
@@ -16,10 +16,11 @@ use rand::Rng;
 use rusqlite::OptionalExtension;
 use std::{
   collections::BTreeMap,
+  sync::Arc,
   time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::Transactor;
+use crate::{serialize, Transactor};
 
 /// Base schema version.
 pub const CURRENT_VERSION: u64 = 1;
@@ -27,7 +28,7 @@ pub const CURRENT_VERSION: u64 = 1;
 /// Stores the metadata for workspaces.
 #[derive(Debug, Clone)]
 pub struct WorkspaceMetadata {
-  prefix: &'static str,
+  prefix: Arc<str>,
   this: u64,
 }
 
@@ -43,16 +44,17 @@ pub trait WorkspaceMetadataTransactor {
 
 impl WorkspaceMetadata {
   /// Creates or loads metadata.
-  pub fn new(prefix: &'static str, txr: &mut impl WorkspaceMetadataTransactor) -> Self {
-    txr.init_version(prefix);
-    txr.init_this(prefix);
-    let version = txr.get_version(prefix).unwrap_or_else(|| {
-      txr.put_version(prefix, CURRENT_VERSION);
+  pub fn new(prefix: impl Into<Arc<str>>, txr: &mut impl WorkspaceMetadataTransactor) -> Self {
+    let prefix = prefix.into();
+    txr.init_version(&prefix);
+    txr.init_this(&prefix);
+    let version = txr.get_version(&prefix).unwrap_or_else(|| {
+      txr.put_version(&prefix, CURRENT_VERSION);
       CURRENT_VERSION
     });
-    let this = txr.get_this(prefix).unwrap_or_else(|| {
+    let this = txr.get_this(&prefix).unwrap_or_else(|| {
       let random = rand::thread_rng().gen();
-      txr.put_this(prefix, random);
+      txr.put_this(&prefix, random);
       random
     });
     if version != CURRENT_VERSION {
@@ -62,9 +64,22 @@ impl WorkspaceMetadata {
     Self { prefix, this }
   }
 
+  /// Loads existing metadata without writing anything, or returns `None` if
+  /// there is none. The tables must exist.
+  pub fn load(prefix: impl Into<Arc<str>>, txr: &impl WorkspaceMetadataTransactor) -> Option<Self> {
+    let prefix = prefix.into();
+    let version = txr.get_version(&prefix)?;
+    let this = txr.get_this(&prefix)?;
+    if version != CURRENT_VERSION {
+      // Reserved for future use.
+      panic!("Unsupported schema version {version}.");
+    }
+    Some(Self { prefix, this })
+  }
+
   /// Returns the name of the workspace.
-  pub fn prefix(&self) -> &'static str {
-    self.prefix
+  pub fn prefix(&self) -> &str {
+    &self.prefix
   }
 
   /// Returns this client's ID.
@@ -141,14 +156,32 @@ impl WorkspaceMetadataTransactor for Transactor {
   }
 }
 
+/// Source of clock values for local writes: given the largest clock value
+/// seen so far plus one, returns the value to use. Clocks are compared as
+/// plain `u64` values everywhere, both when picking the last writer and when
+/// finding actions to sync, so e.g. a hybrid logical clock should keep
+/// physical time in the high bits and a counter in the low bits.
+pub type Clock = fn(u64) -> u64;
+
+/// The default [`Clock`], which returns the current time in nanoseconds, or
+/// `min` if larger. This follows physical time while never going below any
+/// clock value seen, including those from other buckets.
+pub fn system_clock(min: u64) -> u64 {
+  let measured = SystemTime::now().duration_since(UNIX_EPOCH).ok().and_then(|d| u64::try_from(d.as_nanos()).ok());
+  min.max(measured.unwrap_or(0))
+}
+
 /// Stores the metadata for individual Γ-joinable structures.
 #[derive(Debug, Clone)]
 pub struct StructureMetadata {
-  prefix: &'static str,
-  name: &'static str,
+  prefix: Arc<str>,
+  name: Arc<str>,
   buckets: BTreeMap<u64, u64>, // Saved, exhaustive
   mods: BTreeMap<u64, u64>,    // Pending, exhaustive
   next: u64,
+  digest: Option<u128>, // Saved, if there is one
+  digest_mods: u128,    // Pending, to be XORed into `digest`
+  digest_unsaved: bool, // Whether `digest` is yet to be written
 }
 
 /// Database interface for [`StructureMetadata`].
@@ -156,26 +189,57 @@ pub trait StructureMetadataTransactor {
   fn init_buckets(&mut self, prefix: &str, name: &str);
   fn get_buckets(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64>;
   fn set_bucket(&mut self, prefix: &str, name: &str, bucket: u64, clock: u64);
+  fn clear_buckets(&mut self, prefix: &str, name: &str);
+  fn init_digest(&mut self, prefix: &str, name: &str);
+  fn get_digest(&self, prefix: &str, name: &str) -> Option<u128>;
+  fn put_digest(&mut self, prefix: &str, name: &str, digest: u128);
+}
+
+/// Hash of a single item for [`StructureMetadata::digest`], or zero if it has
+/// been removed. This is FNV-1a, and is not meant to resist deliberate
+/// collisions.
+pub fn item_digest<T: serde::Serialize>(id: u128, item: &(u64, u64, Option<T>)) -> u128 {
+  const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+  const PRIME: u128 = 0x0000000001000000000000000000013b;
+  if item.2.is_none() {
+    return 0;
+  }
+  let bytes = serialize(&(id, item)).unwrap();
+  bytes.iter().fold(OFFSET, |hash, byte| (hash ^ *byte as u128).wrapping_mul(PRIME))
 }
 
 impl StructureMetadata {
   /// Creates or loads metadata.
-  pub fn new(prefix: &'static str, name: &'static str, txr: &mut impl StructureMetadataTransactor) -> Self {
-    txr.init_buckets(prefix, name);
-    let buckets = txr.get_buckets(prefix, name);
+  pub fn new(
+    prefix: impl Into<Arc<str>>,
+    name: impl Into<Arc<str>>,
+    txr: &mut impl StructureMetadataTransactor,
+  ) -> Self {
+    let (prefix, name) = (prefix.into(), name.into());
+    txr.init_buckets(&prefix, &name);
+    txr.init_digest(&prefix, &name);
+    Self::load(prefix, name, txr)
+  }
+
+  /// Loads existing metadata without writing anything. The bucket table must
+  /// exist; the digest table may be missing from older databases.
+  pub fn load(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &impl StructureMetadataTransactor) -> Self {
+    let (prefix, name) = (prefix.into(), name.into());
+    let buckets = txr.get_buckets(&prefix, &name);
     let mods = BTreeMap::new();
     let next = buckets.values().fold(0, |acc, &clock| acc.max(clock + 1));
-    Self { prefix, name, buckets, mods, next }
+    let digest = txr.get_digest(&prefix, &name);
+    Self { prefix, name, buckets, mods, next, digest, digest_mods: 0, digest_unsaved: false }
   }
 
   /// Returns the name of the workspace.
-  pub fn prefix(&self) -> &'static str {
-    self.prefix
+  pub fn prefix(&self) -> &str {
+    &self.prefix
   }
 
   /// Returns the name of the structure.
-  pub fn name(&self) -> &'static str {
-    self.name
+  pub fn name(&self) -> &str {
+    &self.name
   }
 
   /// Returns the current clock value for given bucket.
@@ -196,10 +260,27 @@ impl StructureMetadata {
     res
   }
 
-  /// Returns the largest clock value across all buckets plus one.
+  /// Returns the largest clock value across all buckets plus one, or the
+  /// current time in nanoseconds if larger (see [`system_clock`]).
   pub fn next(&self) -> u64 {
-    let measured = SystemTime::now().duration_since(UNIX_EPOCH).ok().and_then(|d| u64::try_from(d.as_nanos()).ok());
-    self.next.max(measured.unwrap_or(0))
+    self.next_with(system_clock)
+  }
+
+  /// Same as [`Self::next`], using `clock` instead. Values below the largest
+  /// clock value plus one are raised to it, so clocks stay strictly increasing.
+  pub fn next_with(&self, clock: Clock) -> u64 {
+    clock(self.next).max(self.next)
+  }
+
+  /// Returns the current clock value for given bucket plus one, or zero for
+  /// an unknown bucket. This is the smallest clock value which [`Self::update`]
+  /// accepts for the bucket, and the only ordering synchronisation relies on.
+  ///
+  /// Local writes should still use [`Self::next`]: the last-writer-wins
+  /// comparison orders items from different buckets by clock value, so a
+  /// bucket with few writes would otherwise always lose.
+  pub fn next_for_bucket(&self, bucket: u64) -> u64 {
+    self.get(bucket).map_or(0, |clock| clock + 1)
   }
 
   /// Updates clock for one bucket.
@@ -212,11 +293,53 @@ impl StructureMetadata {
     false
   }
 
+  /// Returns the XOR of [`item_digest`] over all current items, including
+  /// pending ones, or `None` if it was never saved (see [`Self::set_digest`]).
+  pub fn digest(&self) -> Option<u128> {
+    self.digest.map(|digest| digest ^ self.digest_mods)
+  }
+
+  /// Sets the saved digest, e.g. after computing it for data which predates
+  /// it. It gets written by the next [`Self::save`].
+  pub fn set_digest(&mut self, digest: u128) {
+    self.digest = Some(digest);
+    self.digest_mods = 0;
+    self.digest_unsaved = true;
+  }
+
+  /// Records that an item changed from `prev` to `curr`, given as hashes from
+  /// [`item_digest`].
+  pub fn update_digest(&mut self, prev: u128, curr: u128) {
+    self.digest_mods ^= prev ^ curr;
+  }
+
+  /// Discards all pending modifications. The next clock value is kept, so
+  /// clocks issued afterwards remain strictly increasing.
+  pub fn discard(&mut self) {
+    self.mods.clear();
+    self.digest_mods = 0;
+  }
+
+  /// Removes all clock values, saved or pending. The next clock value is kept,
+  /// so clocks issued afterwards remain strictly increasing.
+  pub fn clear(&mut self, txr: &mut impl StructureMetadataTransactor) {
+    self.buckets.clear();
+    self.mods.clear();
+    txr.clear_buckets(&self.prefix, &self.name);
+    self.digest = Some(0);
+    self.digest_mods = 0;
+    txr.put_digest(&self.prefix, &self.name, 0);
+  }
+
   /// Saves all pending modifications.
   pub fn save(&mut self, txr: &mut impl StructureMetadataTransactor) {
     for (key, value) in std::mem::take(&mut self.mods) {
       self.buckets.insert(key, value);
-      txr.set_bucket(self.prefix, self.name, key, value);
+      txr.set_bucket(&self.prefix, &self.name, key, value);
+    }
+    if let Some(digest) = self.digest().filter(|_| self.digest_mods != 0 || self.digest_unsaved) {
+      (self.digest, self.digest_mods, self.digest_unsaved) = (Some(digest), 0, false);
+      txr.put_digest(&self.prefix, &self.name, digest);
     }
   }
 }
@@ -257,6 +380,45 @@ impl StructureMetadataTransactor for Transactor {
       .execute((bucket.to_be_bytes(), clock.to_be_bytes()))
       .unwrap();
   }
+
+  fn clear_buckets(&mut self, prefix: &str, name: &str) {
+    self.execute_batch(&format!("DELETE FROM \"{prefix}.{name}.buckets\";")).unwrap();
+  }
+
+  fn init_digest(&mut self, prefix: &str, name: &str) {
+    self
+      .execute_batch(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS \"{prefix}.{name}.digest\" (
+          digest BLOB NOT NULL
+        ) STRICT;
+        "
+      ))
+      .unwrap();
+  }
+
+  fn get_digest(&self, prefix: &str, name: &str) -> Option<u128> {
+    // The table is missing from databases created before it was added, and only
+    // opened read-only since.
+    self
+      .prepare_cached(&format!("SELECT digest FROM \"{prefix}.{name}.digest\""))
+      .ok()?
+      .query_row((), |row| {
+        let digest = row.get(0).unwrap();
+        Ok(u128::from_be_bytes(digest))
+      })
+      .optional()
+      .unwrap()
+  }
+
+  fn put_digest(&mut self, prefix: &str, name: &str, digest: u128) {
+    self.execute_batch(&format!("DELETE FROM \"{prefix}.{name}.digest\";")).unwrap();
+    self
+      .prepare_cached(&format!("INSERT INTO \"{prefix}.{name}.digest\" VALUES (?)"))
+      .unwrap()
+      .execute((digest.to_be_bytes(),))
+      .unwrap();
+  }
 }
 
 #[cfg(test)]
@@ -302,11 +464,21 @@ mod tests {
     assert_eq!(structure.buckets().get(&2).unwrap(), &3);
     structure.update(2, 2u64);
     assert_eq!(structure.buckets().get(&2).unwrap(), &3);
+    assert_eq!(structure.next_for_bucket(1), 5);
+    assert_eq!(structure.next_for_bucket(2), 4);
+    assert_eq!(structure.next_for_bucket(3), 0);
 
     structure.save(&mut txr);
     assert_eq!(structure.buckets().get(&1).unwrap(), &4);
     assert_eq!(structure.buckets().get(&2).unwrap(), &3);
 
+    structure.update(1, 5u64);
+    structure.update(4, 1u64);
+    structure.discard();
+    assert_eq!(structure.buckets(), BTreeMap::from([(1, 4u64), (2, 3u64)]));
+    assert!(structure.next() >= 6);
+    assert_eq!(structure.buckets().get(&2).unwrap(), &3);
+
     let mut structure = StructureMetadata::new("workspace", "name", &mut txr);
     assert_eq!(structure.prefix(), "workspace");
     assert_eq!(structure.name(), "name");
@@ -12,10 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rusqlite::{OptionalExtension, Result, Row};
-use std::collections::{btree_map::Entry, BTreeMap};
+use rusqlite::{params_from_iter, OptionalExtension, Result, Row};
+use std::{
+  collections::{btree_map::Entry, BTreeMap},
+  sync::Arc,
+};
 
-use super::metadata::{StructureMetadata, StructureMetadataTransactor};
+use super::{
+  metadata::{item_digest, Clock, StructureMetadata, StructureMetadataTransactor},
+  Outcome, MAX_PARAMS,
+};
 use crate::Transactor;
 
 /// A last-writer-wins element set for storing nodes.
@@ -36,27 +42,55 @@ fn item_lt(lhs: &Item, rhs: &Item) -> bool {
 pub trait NodeSetTransactor: StructureMetadataTransactor {
   fn init(&mut self, prefix: &str, name: &str);
   fn get(&self, prefix: &str, name: &str, id: u128) -> Option<Item>;
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> BTreeMap<u128, Item>;
   fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item);
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> usize;
   fn id_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, ()>;
   fn by_bucket_clock_range(&self, prefix: &str, name: &str, bucket: u64, lower: Option<u64>) -> BTreeMap<u128, Item>;
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64>;
 }
 
 impl NodeSet {
   /// Creates or loads data.
-  pub fn new(prefix: &'static str, name: &'static str, txr: &mut impl NodeSetTransactor) -> Self {
-    let metadata = StructureMetadata::new(prefix, name, txr);
+  pub fn new(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &mut impl NodeSetTransactor) -> Self {
+    let (prefix, name) = (prefix.into(), name.into());
+    let metadata = StructureMetadata::new(prefix.clone(), name.clone(), txr);
     let mods = BTreeMap::new();
-    txr.init(prefix, name);
-    Self { metadata, mods }
+    txr.init(&prefix, &name);
+    let mut res = Self { metadata, mods };
+    res.init_digest(txr);
+    res
+  }
+
+  /// Loads existing data without creating or altering any tables, e.g. on a
+  /// read-only connection. The tables must exist.
+  pub fn load(prefix: impl Into<Arc<str>>, name: impl Into<Arc<str>>, txr: &impl NodeSetTransactor) -> Self {
+    let metadata = StructureMetadata::load(prefix, name, txr);
+    let mut res = Self { metadata, mods: BTreeMap::new() };
+    res.init_digest(txr);
+    res
+  }
+
+  /// Computes the digest by scanning all items, if none was saved before.
+  fn init_digest(&mut self, txr: &impl NodeSetTransactor) {
+    if self.metadata.digest().is_none() {
+      let actions = self.actions(txr, BTreeMap::new());
+      self.metadata.set_digest(actions.iter().fold(0, |acc, (id, item)| acc ^ item_digest(*id, item)));
+    }
+  }
+
+  /// See [`super::atom_set::AtomSet::digest`].
+  pub fn digest(&self) -> u128 {
+    self.metadata.digest().unwrap_or_default()
   }
 
   /// Returns the name of the workspace.
-  pub fn prefix(&self) -> &'static str {
+  pub fn prefix(&self) -> &str {
     self.metadata.prefix()
   }
 
   /// Returns the name of the structure.
-  pub fn name(&self) -> &'static str {
+  pub fn name(&self) -> &str {
     self.metadata.name()
   }
 
@@ -70,11 +104,25 @@ impl NodeSet {
     self.metadata.next()
   }
 
-  /// Returns pending modifications.
+  /// Same as [`Self::next`], using `clock` instead of the system time.
+  pub fn next_with(&self, clock: Clock) -> u64 {
+    self.metadata.next_with(clock)
+  }
+
+  /// Returns the current clock value for given bucket plus one.
+  pub fn next_for_bucket(&self, bucket: u64) -> u64 {
+    self.metadata.next_for_bucket(bucket)
+  }
+
+  /// Returns pending modifications, leaving out those which end up with the
+  /// same value as before (see [`super::atom_set::AtomSet::mods`]).
   pub fn mods(&self) -> Vec<(u128, Option<u64>, Option<u64>)> {
     let mut res = Vec::new();
     for (id, (prev, curr)) in &self.mods {
-      res.push((*id, prev.and_then(|(_, _, l)| l), curr.2));
+      let prev = prev.and_then(|(_, _, l)| l);
+      if prev != curr.2 {
+        res.push((*id, prev, curr.2));
+      }
     }
     res
   }
@@ -87,6 +135,19 @@ impl NodeSet {
     self.mods.get(&id).map_or_else(|| txr.get(self.prefix(), self.name(), id), |(_, curr)| Some(*curr))
   }
 
+  /// Equivalent to calling [`Self::get`] for each of `ids`, but fetches
+  /// committed data in as few queries as possible. Absent ids are omitted.
+  pub fn get_many(&self, txr: &impl NodeSetTransactor, ids: &[u128]) -> BTreeMap<u128, Item> {
+    let unmodified = ids.iter().copied().filter(|id| !self.mods.contains_key(id)).collect::<Vec<_>>();
+    let mut res = txr.get_many(self.prefix(), self.name(), &unmodified);
+    for id in ids {
+      if let Some((_, curr)) = self.mods.get(id) {
+        res.insert(*id, *curr);
+      }
+    }
+    res
+  }
+
   pub fn id_by_label(&self, txr: &impl NodeSetTransactor, label: u64) -> BTreeMap<u128, ()> {
     let mut res = txr.id_by_label(self.prefix(), self.name(), label);
     for (id, (_, (_, _, l))) in &self.mods {
@@ -127,12 +188,14 @@ impl NodeSet {
         Entry::Vacant(entry) => {
           let prev = txr.get(self.metadata.prefix(), self.metadata.name(), id);
           if prev.is_none() || item_lt(prev.as_ref().unwrap(), &item) {
+            self.metadata.update_digest(prev.as_ref().map_or(0, |prev| item_digest(id, prev)), item_digest(id, &item));
             entry.insert((prev, item));
             return true;
           }
         }
         Entry::Occupied(mut entry) => {
           if item_lt(&entry.get().1, &item) {
+            self.metadata.update_digest(item_digest(id, &entry.get().1), item_digest(id, &item));
             entry.get_mut().1 = item;
             return true;
           }
@@ -142,6 +205,77 @@ impl NodeSet {
     false
   }
 
+  /// Modifies items in order, reporting for each whether it was applied. The
+  /// comparison is made against the current item, including pending ones.
+  pub fn join(
+    &mut self,
+    txr: &impl NodeSetTransactor,
+    actions: impl IntoIterator<Item = (u128, Item)>,
+  ) -> Vec<(u128, Outcome)> {
+    let mut res = Vec::new();
+    for (id, item) in actions {
+      let outcome = match self.get(txr, id) {
+        Some(curr) if (curr.0, curr.1) == (item.0, item.1) => Outcome::RejectedTie,
+        Some(curr) if !item_lt(&curr, &item) => Outcome::RejectedOlder,
+        curr => {
+          let (bucket, clock, l) = item;
+          match (self.set(txr, id, bucket, clock, l), curr) {
+            (true, None) => Outcome::Inserted,
+            (true, Some(_)) => Outcome::Applied,
+            (false, _) => Outcome::RejectedOlder,
+          }
+        }
+      };
+      res.push((id, outcome));
+    }
+    res
+  }
+
+  /// Returns `(recorded, found)` clock values for buckets where data has
+  /// been written past the recorded clock value (see [`Self::recover`]).
+  pub fn clocks_behind(&self, txr: &impl NodeSetTransactor) -> BTreeMap<u64, (Option<u64>, u64)> {
+    let buckets = self.buckets();
+    let found = txr.max_clock_by_bucket(self.prefix(), self.name());
+    found
+      .into_iter()
+      .map(|(bucket, clock)| (bucket, (buckets.get(&bucket).copied(), clock)))
+      .filter(|(_, (recorded, found))| *recorded < Some(*found))
+      .collect()
+  }
+
+  /// Raises saved clock values to the largest ones present in data, in case
+  /// data was written without its metadata (e.g. from an interrupted save).
+  /// Returns whether anything was repaired.
+  pub fn recover(&mut self, txr: &mut impl NodeSetTransactor) -> bool {
+    let mut res = false;
+    for (bucket, clock) in txr.max_clock_by_bucket(self.prefix(), self.name()) {
+      res |= self.metadata.update(bucket, clock);
+    }
+    self.metadata.save(txr);
+    res
+  }
+
+  /// Discards all pending modifications.
+  pub fn discard(&mut self) {
+    self.metadata.discard();
+    self.mods.clear();
+  }
+
+  /// Physically deletes saved removals with clock values no larger than those
+  /// in `acknowledged`, returning the number deleted. Pending modifications are
+  /// not affected.
+  ///
+  /// Removals are otherwise kept forever, so that they can be sent to peers.
+  /// Only call this with values which every peer is known to have seen, or
+  /// peers may never learn of the removals. This is also what keeps deleted
+  /// items from coming back: the metadata only rejects clock values already
+  /// seen from the same bucket, so an older write from another bucket, not
+  /// yet seen here, would be accepted. Once every peer has seen a removal,
+  /// none of them holds such a write as its current item to send.
+  pub fn remove_tombstones(&mut self, txr: &mut impl NodeSetTransactor, acknowledged: &BTreeMap<u64, u64>) -> usize {
+    acknowledged.iter().map(|(&bucket, &upper)| txr.remove_tombstones(self.prefix(), self.name(), bucket, upper)).sum()
+  }
+
   /// Saves all pending modifications.
   pub fn save(&mut self, txr: &mut impl NodeSetTransactor) {
     self.metadata.save(txr);
@@ -202,6 +336,22 @@ impl NodeSetTransactor for Transactor {
       .map(|(_, item)| item)
   }
 
+  fn get_many(&self, prefix: &str, name: &str, ids: &[u128]) -> BTreeMap<u128, Item> {
+    let mut res = BTreeMap::new();
+    for chunk in ids.chunks(MAX_PARAMS) {
+      let params = vec!["?"; chunk.len()].join(", ");
+      let mut stmt = self
+        .prepare_cached(&format!(
+          "SELECT id, bucket, clock, label FROM \"{prefix}.{name}.data\"
+          WHERE id IN ({params})"
+        ))
+        .unwrap();
+      let rows = stmt.query_map(params_from_iter(chunk.iter().map(|id| id.to_be_bytes())), |row| Ok(read_row(row)));
+      res.extend(rows.unwrap().map(Result::unwrap));
+    }
+    res
+  }
+
   fn set(&mut self, prefix: &str, name: &str, id: u128, item: Item) {
     self
       .prepare_cached(&format!("REPLACE INTO \"{prefix}.{name}.data\" VALUES (?, ?, ?, ?)"))
@@ -210,6 +360,17 @@ impl NodeSetTransactor for Transactor {
       .unwrap();
   }
 
+  fn remove_tombstones(&mut self, prefix: &str, name: &str, bucket: u64, upper: u64) -> usize {
+    self
+      .prepare_cached(&format!(
+        "DELETE FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        WHERE bucket = ? AND clock <= ? AND label IS NULL"
+      ))
+      .unwrap()
+      .execute((bucket.to_be_bytes(), upper.to_be_bytes()))
+      .unwrap()
+  }
+
   fn id_by_label(&self, prefix: &str, name: &str, label: u64) -> BTreeMap<u128, ()> {
     self
       .prepare_cached(&format!(
@@ -227,7 +388,7 @@ impl NodeSetTransactor for Transactor {
     self
       .prepare_cached(&format!(
         "SELECT id, bucket, clock, label FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
-        WHERE bucket = ? AND clock > ?"
+        WHERE bucket = ? AND clock > ifnull(?, X'')"
       ))
       .unwrap()
       .query_map((bucket.to_be_bytes(), lower.map(u64::to_be_bytes)), |row| Ok(read_row(row)))
@@ -235,4 +396,21 @@ impl NodeSetTransactor for Transactor {
       .map(Result::unwrap)
       .collect()
   }
+
+  fn max_clock_by_bucket(&self, prefix: &str, name: &str) -> BTreeMap<u64, u64> {
+    self
+      .prepare_cached(&format!(
+        "SELECT bucket, MAX(clock) FROM \"{prefix}.{name}.data\" INDEXED BY \"{prefix}.{name}.data.idx_bucket_clock\"
+        GROUP BY bucket"
+      ))
+      .unwrap()
+      .query_map((), |row| {
+        let bucket = row.get(0).unwrap();
+        let clock = row.get(1).unwrap();
+        Ok((u64::from_be_bytes(bucket), u64::from_be_bytes(clock)))
+      })
+      .unwrap()
+      .map(Result::unwrap)
+      .collect()
+  }
 }